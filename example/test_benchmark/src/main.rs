@@ -14,7 +14,7 @@ pub fn cur_timestamp_millis() -> i64{
 fn usage(program: &str, opts: &Options) {
     let program_path = std::path::PathBuf::from(program);
     let program_name = program_path.file_stem().unwrap().to_str().unwrap();
-    let brief = format!("Usage: {} [-p] [tcp|raknet] [-t] [server|client] [-a] [IP_ADDRESS]",
+    let brief = format!("Usage: {} [-p] [tcp|raknet] [-t] [server|client] [-a] [IP_ADDRESS] [-m] [latency|throughput]",
                         program_name);
     print!("{}", opts.usage(&brief));
 }
@@ -41,6 +41,11 @@ async fn main() {
                 "bind or connect connection address",
                 "ADDRESS");
 
+	opts.optopt("m",
+                "mode",
+                "latency or throughput , default is latency",
+                "MODE");
+
     let matches = opts.parse(&args[1..]).unwrap_or_else(|_| {
         usage(&program, &opts);
         std::process::exit(-1);
@@ -49,6 +54,12 @@ async fn main() {
     let proto = matches.opt_str("p").unwrap();
     let ctype = matches.opt_str("t").unwrap();
     let address = matches.opt_str("a").unwrap();
+    let mode = matches.opt_str("m").unwrap_or_else(|| "latency".to_string());
+
+    if mode == "throughput"{
+        throughput(&proto, &ctype, &address).await;
+        return;
+    }
 
     if proto == "tcp"{
         if ctype == "client"{
@@ -123,3 +134,68 @@ async fn main() {
         }
     }
 }
+
+const THROUGHPUT_PACKET_SIZE : usize = 1000;
+const THROUGHPUT_PACKET_COUNT : usize = 10000;
+
+// The server sends THROUGHPUT_PACKET_COUNT packets back-to-back, the client prints how long it takes to receive all of them.
+// Use `tc qdisc add dev lo root netem delay 50ms` to simulate a high RTT link.
+async fn throughput(proto : &str, ctype : &str, address : &str){
+    if proto == "tcp"{
+        if ctype == "client"{
+            let mut client = TcpStream::connect(address).await.unwrap();
+            let mut buf = [0u8;THROUGHPUT_PACKET_SIZE];
+            let t1 = cur_timestamp_millis();
+            for _ in 0..THROUGHPUT_PACKET_COUNT{
+                client.read_exact(&mut buf).await.unwrap();
+            }
+            print_throughput(cur_timestamp_millis() - t1);
+        }else if ctype == "server"{
+            let server = TcpListener::bind(address).await.unwrap();
+            loop{
+                let (mut client, _) = server.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let buf = [0xfe;THROUGHPUT_PACKET_SIZE];
+                    for _ in 0..THROUGHPUT_PACKET_COUNT{
+                        client.write_all(&buf).await.unwrap();
+                    }
+
+                    //avoid connection closed
+                    sleep(std::time::Duration::from_secs(100)).await;
+                });
+            }
+        }
+    }else if proto == "raknet" {
+        if ctype == "client"{
+            let client = RaknetSocket::connect(&address.parse().unwrap()).await.unwrap();
+            let t1 = cur_timestamp_millis();
+            for _ in 0..THROUGHPUT_PACKET_COUNT{
+                let _ = client.recv().await.unwrap();
+            }
+            print_throughput(cur_timestamp_millis() - t1);
+        }else if ctype == "server"{
+            let mut server = RaknetListener::bind(&address.parse().unwrap()).await.unwrap();
+            server.listen().await;
+            loop{
+                let client = server.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let buf = [0xfe;THROUGHPUT_PACKET_SIZE];
+                    for _ in 0..THROUGHPUT_PACKET_COUNT{
+                        client.send(&buf, Reliability::ReliableOrdered).await.unwrap();
+                    }
+                    client.flush().await.unwrap();
+
+                    //avoid connection closed
+                    sleep(std::time::Duration::from_secs(100)).await;
+                });
+            }
+        }
+    }
+}
+
+fn print_throughput(t : i64){
+    let t = t.max(1);
+    let bytes = THROUGHPUT_PACKET_SIZE * THROUGHPUT_PACKET_COUNT;
+    println!("time : {} ms" , t);
+    println!("throughput : {} KB/s" , bytes as i64 * 1000 / t / 1024);
+}
@@ -1,9 +1,21 @@
-use std::{collections::HashMap, net::SocketAddr};
-
-use crate::{datatype::*, error::*, fragment::FragmentQ, raknet_log_debug, utils::*};
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    net::SocketAddr,
+};
+
+use tokio::sync::oneshot;
+
+use crate::{
+    congestion::*,
+    datatype::*,
+    error::*,
+    fragment::{FragmentLimits, FragmentQ},
+    raknet_log_debug,
+    utils::*,
+};
 
 /// Enumeration type options for Raknet transport reliability
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Reliability {
     /// Unreliable packets are sent by straight UDP. They may arrive out of order, or not at all. This is best for data that is unimportant, or data that you send very frequently so even if some packets are missed newer packets will compensate.
     /// Advantages - These packets don't need to be acknowledged by the network, saving the size of a UDP header in acknowledgment (about 50 bytes or so). The savings can really add up.
@@ -23,6 +35,15 @@ pub enum Reliability {
     /// Advantages - You get the reliability of UDP packets, the ordering of ordered packets, yet don't have to wait for old packets. More packets will arrive with this method than with the unreliable sequenced method, and they will be distributed more evenly. The most important advantage however is that the latest packet sent will arrive, where with unreliable sequenced the latest packet sent may not arrive.
     /// Disadvantages - Wasteful of bandwidth because it uses the overhead of reliable UDP packets to ensure late packets arrive that just get ignored anyway.
     ReliableSequenced = 0x04,
+    /// Same as Unreliable, and the sender is told whether the packet was acknowledged or lost.
+    /// It is sent as Unreliable on the wire.
+    UnreliableWithAckReceipt = 0x05,
+    /// Same as Reliable, and the sender is told when the packet was acknowledged.
+    /// It is sent as Reliable on the wire.
+    ReliableWithAckReceipt = 0x06,
+    /// Same as ReliableOrdered, and the sender is told when the packet was acknowledged.
+    /// It is sent as ReliableOrdered on the wire.
+    ReliableOrderedWithAckReceipt = 0x07,
 }
 
 impl Reliability {
@@ -33,6 +54,9 @@ impl Reliability {
             Reliability::Reliable => 0x02,
             Reliability::ReliableOrdered => 0x03,
             Reliability::ReliableSequenced => 0x04,
+            Reliability::UnreliableWithAckReceipt => 0x05,
+            Reliability::ReliableWithAckReceipt => 0x06,
+            Reliability::ReliableOrderedWithAckReceipt => 0x07,
         }
     }
 
@@ -43,9 +67,53 @@ impl Reliability {
             0x02 => Ok(Reliability::Reliable),
             0x03 => Ok(Reliability::ReliableOrdered),
             0x04 => Ok(Reliability::ReliableSequenced),
+            0x05 => Ok(Reliability::UnreliableWithAckReceipt),
+            0x06 => Ok(Reliability::ReliableWithAckReceipt),
+            0x07 => Ok(Reliability::ReliableOrderedWithAckReceipt),
             _ => Err(RaknetError::IncorrectReliability),
         }
     }
+
+    /// the reliability used on the wire , ack receipts only matter to the sender
+    pub fn without_ack_receipt(&self) -> Self {
+        match self {
+            Reliability::UnreliableWithAckReceipt => Reliability::Unreliable,
+            Reliability::ReliableWithAckReceipt => Reliability::Reliable,
+            Reliability::ReliableOrderedWithAckReceipt => Reliability::ReliableOrdered,
+            r => r.clone(),
+        }
+    }
+
+    pub fn with_ack_receipt(&self) -> bool {
+        matches!(
+            self,
+            Reliability::UnreliableWithAckReceipt
+                | Reliability::ReliableWithAckReceipt
+                | Reliability::ReliableOrderedWithAckReceipt
+        )
+    }
+}
+
+/// Enumeration type options for the send priority of a packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Sent on the next flush without waiting for the send tick.
+    Immediate = 0,
+    /// Sent at the next send tick, before the lower priorities.
+    High = 1,
+    /// Sent at the next send tick, after the higher priorities.
+    Medium = 2,
+    /// Sent at the next send tick, when there is room left.
+    /// Lower priorities are weighted, so they are delayed but never starved.
+    Low = 3,
+}
+
+impl Priority {
+    /// the weight a priority adds per queued packet , same as raknet
+    fn weight_step(&self) -> u64 {
+        let p = *self as u64;
+        (1 << p) * p + p
+    }
 }
 
 const NEEDS_B_AND_AS_FLAG: u8 = 0x4;
@@ -53,7 +121,6 @@ const CONTINUOUS_SEND_FLAG: u8 = 0x8;
 
 #[derive(Clone)]
 pub struct FrameSetPacket {
-    pub id: u8,
     pub sequence_number: u32,
     pub flags: u8,
     pub length_in_bytes: u16,
@@ -65,6 +132,8 @@ pub struct FrameSetPacket {
     pub compound_id: u16,
     pub fragment_index: u32,
     pub data: Vec<u8>,
+    /// ack receipt of the message this frame belongs to , it is not sent on the wire
+    pub receipt_id: Option<u32>,
 }
 
 impl FrameSetPacket {
@@ -72,7 +141,6 @@ impl FrameSetPacket {
         let flag = r.to_u8() << 5;
 
         FrameSetPacket {
-            id: 0,
             sequence_number: 0,
             flags: flag,
             length_in_bytes: data.len() as u16,
@@ -84,6 +152,7 @@ impl FrameSetPacket {
             compound_id: 0,
             fragment_index: 0,
             data,
+            receipt_id: None,
         }
     }
 
@@ -91,7 +160,6 @@ impl FrameSetPacket {
         let mut reader = RaknetReader::new(buf);
 
         let mut ret = Self {
-            id: 0,
             sequence_number: 0,
             flags: 0,
             length_in_bytes: 0,
@@ -103,9 +171,10 @@ impl FrameSetPacket {
             compound_id: 0,
             fragment_index: 0,
             data: vec![],
+            receipt_id: None,
         };
 
-        ret.id = reader.read_u8().unwrap();
+        reader.read_u8().unwrap();
         ret.sequence_number = reader.read_u24(Endian::Little).unwrap();
 
         //Top 3 bits are reliability type
@@ -141,6 +210,7 @@ impl FrameSetPacket {
         Ok((ret, reader.pos() == buf.len() as u64))
     }
 
+    #[allow(dead_code)]
     pub fn serialize(&self) -> Result<Vec<u8>> {
         let mut writer = RaknetWriter::new();
 
@@ -198,7 +268,7 @@ impl FrameSetPacket {
     }
 
     pub fn is_reliable(&self) -> Result<bool> {
-        let r = Reliability::from((self.flags & 224) >> 5)?;
+        let r = Reliability::from((self.flags & 224) >> 5)?.without_ack_receipt();
         Ok(matches!(
             r,
             Reliability::Reliable | Reliability::ReliableOrdered | Reliability::ReliableSequenced
@@ -206,7 +276,7 @@ impl FrameSetPacket {
     }
 
     pub fn is_ordered(&self) -> Result<bool> {
-        let r = Reliability::from((self.flags & 224) >> 5)?;
+        let r = Reliability::from((self.flags & 224) >> 5)?.without_ack_receipt();
         Ok(matches!(
             r,
             Reliability::UnreliableSequenced
@@ -226,12 +296,9 @@ impl FrameSetPacket {
         Reliability::from((self.flags & 224) >> 5)
     }
 
-    pub fn _size(&self) -> Result<usize> {
+    /// size of this frame inside a frame set, the datagram header is not included.
+    pub fn size(&self) -> Result<usize> {
         let mut ret = 0;
-        // id
-        ret += 1;
-        // sequence number
-        ret += 3;
         // flags
         ret += 1;
         // length_in_bits
@@ -259,6 +326,7 @@ impl FrameSetPacket {
     }
 }
 
+#[derive(Clone)]
 pub struct FrameVec {
     pub id: u8,
     pub sequence_number: u32,
@@ -266,6 +334,9 @@ pub struct FrameVec {
 }
 
 impl FrameVec {
+    /// id(1) + sequence number(3)
+    pub const HEADER_SIZE: usize = 4;
+
     pub fn new(buf: Vec<u8>) -> Result<Self> {
         let mut ret = Self {
             id: 0,
@@ -282,7 +353,6 @@ impl FrameVec {
 
         while reader.pos() < size.try_into().unwrap() {
             let mut frame = FrameSetPacket {
-                id: ret.id,
                 sequence_number: ret.sequence_number,
                 flags: 0,
                 length_in_bytes: 0,
@@ -294,6 +364,7 @@ impl FrameVec {
                 compound_id: 0,
                 fragment_index: 0,
                 data: vec![],
+                receipt_id: None,
             };
 
             //Top 3 bits are reliability type
@@ -334,7 +405,16 @@ impl FrameVec {
     pub fn _serialize(&self) -> Result<Vec<u8>> {
         let mut writer = RaknetWriter::new();
 
-        let id = 0x80 | 4 | 8;
+        let mut id = 0x80 | NEEDS_B_AND_AS_FLAG;
+
+        //set fragment flag , first fragment frame id == 0x84
+        if self
+            .frames
+            .iter()
+            .any(|x| x.is_fragment() && x.fragment_index != 0)
+        {
+            id |= CONTINUOUS_SEND_FLAG;
+        }
 
         writer.write_u8(id).unwrap();
         writer
@@ -368,8 +448,8 @@ impl FrameVec {
             }
 
             //fourth bit is 1 when the frame is fragmented and part of a compound.
-            //flags and 8 [0000 1000(b)] == if fragmented
-            if (frame.flags & 0x08) != 0 {
+            //flags and 16 [0001 0000(b)] == if fragmented
+            if (frame.flags & 16) != 0 {
                 writer.write_u32(frame.compound_size, Endian::Big).unwrap();
                 writer.write_u16(frame.compound_id, Endian::Big).unwrap();
                 writer.write_u32(frame.fragment_index, Endian::Big).unwrap();
@@ -379,33 +459,122 @@ impl FrameVec {
 
         Ok(writer.get_raw_payload())
     }
+
+    pub fn size(&self) -> Result<usize> {
+        let mut ret = FrameVec::HEADER_SIZE;
+        for frame in &self.frames {
+            ret += frame.size()?;
+        }
+        Ok(ret)
+    }
+}
+
+/// How much reordering a connection tolerates before it considers a datagram lost.
+///
+/// A missing datagram is lost once `packets` newer datagrams have arrived, or it has been missing for `millis` milliseconds.
+/// The sender waits `millis` more than the round trip time.
+#[derive(Clone, Debug)]
+pub struct ReorderThreshold {
+    /// Default is 3, the duplicate ack threshold of TCP.
+    pub packets: u32,
+    /// Default is 20ms.
+    pub millis: i64,
+}
+
+impl Default for ReorderThreshold {
+    fn default() -> Self {
+        Self {
+            packets: 3,
+            millis: 20,
+        }
+    }
+}
+
+/// Constants of the retransmission timeout estimator (RFC 6298).
+///
+/// RTO = SRTT + max(granularity, k * RTTVAR), bounded by `min_rto` and `max_rto`.
+#[derive(Clone, Debug)]
+pub struct RtoOptions {
+    /// Default is 50ms, RFC 6298 says 1s which is too long for games.
+    pub min_rto: i64,
+    /// Default is 12000ms.
+    pub max_rto: i64,
+    /// RTO before the first round trip time sample. Default is 1000ms.
+    pub initial_rto: i64,
+    /// Gain of SRTT. Default is 1/8.
+    pub alpha: f64,
+    /// Gain of RTTVAR. Default is 1/4.
+    pub beta: f64,
+    /// Default is 4.
+    pub k: f64,
+    /// Clock granularity, the send tick. Default is 50ms.
+    pub granularity: i64,
+}
+
+impl Default for RtoOptions {
+    fn default() -> Self {
+        Self {
+            min_rto: 50,
+            max_rto: 12000,
+            initial_rto: 1000,
+            alpha: 0.125,
+            beta: 0.25,
+            k: 4.0,
+            granularity: SendQ::DEFAULT_TIMEOUT_MILLS,
+        }
+    }
 }
 
 pub struct ACKSet {
     ack: Vec<(u32, u32)>,
     nack: Vec<(u32, u32)>,
-    last_max: u32,
+    // ranges of sequence numbers which may still arrive reordered , begin , end , tick they were found missing
+    missing: Vec<(u32, u32, i64)>,
+    // ranges of sequence numbers nacked which may still arrive late , begin , end , tick they were nacked
+    lost: VecDeque<(u32, u32, i64)>,
+    // the sequence number expected next , older ones are duplicates or late
+    next: u32,
+    reorder_threshold: ReorderThreshold,
 }
 
 impl ACKSet {
+    /// nacked sequence numbers arriving later than this are treated as duplicates
+    const LOST_MEMORY_MILLIS: i64 = 10000;
+    const MAX_LOST_RANGES: usize = 1024;
+
     pub fn new() -> Self {
         ACKSet {
             ack: vec![],
             nack: vec![],
-            last_max: 0,
+            missing: vec![],
+            lost: VecDeque::new(),
+            next: 0,
+            reorder_threshold: ReorderThreshold::default(),
         }
     }
+
+    pub fn set_reorder_threshold(&mut self, threshold: ReorderThreshold) {
+        self.reorder_threshold = threshold;
+    }
+
     pub fn insert(&mut self, s: u32) {
-        if s != 0 {
-            if s > self.last_max && s != self.last_max + 1 {
-                self.nack.push((self.last_max + 1, s - 1));
+        self.advance(s);
+        self.remove_missing(s);
+        if let Some(i) = self.lost.iter().position(|x| x.0 <= s && s <= x.1) {
+            let (begin, end, tick) = self.lost.remove(i).unwrap();
+            if s != end {
+                self.lost.push_back((s + 1, end, tick));
             }
-
-            if s > self.last_max {
-                self.last_max = s;
+            if s != begin {
+                self.lost.push_back((begin, s - 1, tick));
             }
         }
 
+        // all frames of a frame set share the same sequence number
+        if self.ack.iter().any(|a| a.0 <= s && s <= a.1) {
+            return;
+        }
+
         for i in 0..self.ack.len() {
             let a = self.ack[i];
             if a.0 != 0 && s == a.0 - 1 {
@@ -420,13 +589,92 @@ impl ACKSet {
         self.ack.push((s, s));
     }
 
+    /// Report a received datagram as lost , the peer will resend it.
+    pub fn nack(&mut self, s: u32) {
+        self.advance(s);
+        self.remove_missing(s);
+        self.nack.push((s, s));
+    }
+
+    /// Whether a datagram with this sequence number has been received already.
+    pub fn is_received(&self, s: u32) -> bool {
+        u24_lt(s, self.next)
+            && !self.missing.iter().any(|x| x.0 <= s && s <= x.1)
+            && !self.lost.iter().any(|x| x.0 <= s && s <= x.1)
+    }
+
+    // the gap between the expected sequence number and a newer one may be lost
+    fn advance(&mut self, s: u32) {
+        if u24_ge(s, self.next) {
+            if s != self.next {
+                let tick = cur_timestamp_millis();
+                let last = u24_sub(s, 1);
+                // a range on the wire can not wrap
+                if last < self.next {
+                    self.missing.push((self.next, U24_MAX, tick));
+                    self.missing.push((0, last, tick));
+                } else {
+                    self.missing.push((self.next, last, tick));
+                }
+            }
+            self.next = u24_add(s, 1);
+        }
+    }
+
+    // a reordered datagram has arrived
+    fn remove_missing(&mut self, s: u32) {
+        if let Some(i) = self.missing.iter().position(|x| x.0 <= s && s <= x.1) {
+            let (begin, end, tick) = self.missing.remove(i);
+            if s != end {
+                self.missing.insert(i, (s + 1, end, tick));
+            }
+            if s != begin {
+                self.missing.insert(i, (begin, s - 1, tick));
+            }
+        }
+    }
+
     pub fn get_ack(&mut self) -> Vec<(u32, u32)> {
         let ret = self.ack.clone();
         self.ack.clear();
         ret
     }
 
-    pub fn get_nack(&mut self) -> Vec<(u32, u32)> {
+    /// Return the lost sequence numbers , the missing ones beyond the reorder threshold.
+    pub fn get_nack(&mut self, tick: i64) -> Vec<(u32, u32)> {
+        // sequence numbers this old have been overtaken by enough newer datagrams
+        let newest = u24_sub(self.next, 1);
+        let overtaken = u24_sub(newest, self.reorder_threshold.packets);
+
+        let mut missing = vec![];
+        let mut lost = vec![];
+        for (begin, end, missing_tick) in std::mem::take(&mut self.missing) {
+            if tick - missing_tick >= self.reorder_threshold.millis {
+                lost.push((begin, end));
+            } else if u24_ge(overtaken, begin) {
+                if u24_ge(overtaken, end) {
+                    lost.push((begin, end));
+                } else {
+                    lost.push((begin, overtaken));
+                    missing.push((overtaken + 1, end, missing_tick));
+                }
+            } else {
+                missing.push((begin, end, missing_tick));
+            }
+        }
+        self.missing = missing;
+
+        // remember the nacked ranges for a while , the originals may still arrive
+        self.lost
+            .retain(|x| tick - x.2 < ACKSet::LOST_MEMORY_MILLIS);
+        for (begin, end) in lost {
+            self.nack.push((begin, end));
+            self.lost.push_back((begin, end, tick));
+        }
+        while self.lost.len() > ACKSet::MAX_LOST_RANGES {
+            self.lost.pop_front();
+        }
+
         let ret = self.nack.clone();
         self.nack.clear();
         ret
@@ -434,88 +682,205 @@ impl ACKSet {
 }
 
 pub struct RecvQ {
-    sequenced_frame_index: u32,
-    last_ordered_index: u32,
+    sequenced_frame_index: [u32; NUMBER_OF_ORDER_CHANNELS as usize],
+    last_ordered_index: [u32; NUMBER_OF_ORDER_CHANNELS as usize],
     sequence_number_ackset: ACKSet,
-    packets: HashMap<u32, FrameSetPacket>,
-    ordered_packets: HashMap<u32, FrameSetPacket>,
+    // reliable frame indices older than it have all been received
+    reliable_base_index: u32,
+    // whether reliable_base_index + i has been received , like the hole count queue of raknet
+    reliable_window: VecDeque<bool>,
+    packets: Vec<FrameSetPacket>,
+    ordered_packets: Vec<HashMap<u32, FrameSetPacket>>,
+    // bytes of the frames in ordered_packets
+    window_bytes: usize,
+    max_window_bytes: usize,
+    // datagrams dropped since the window last drained
+    window_violations: u32,
     fragment_queue: FragmentQ,
 }
 
 impl RecvQ {
+    /// maximum of reliable frames received ahead of the oldest missing one
+    pub const MAX_RELIABLE_WINDOW: usize = 128 * 1024;
+    /// default maximum of bytes of the ordered frames waiting for a missing one
+    pub const DEFAULT_WINDOW_BYTES: usize = 4 * 1024 * 1024;
+    /// a peer whose datagrams are dropped more often than this without letting the window drain is disconnected
+    pub const MAX_WINDOW_VIOLATIONS: u32 = 256;
+
     pub fn new() -> Self {
         Self {
             sequence_number_ackset: ACKSet::new(),
-            packets: HashMap::new(),
+            reliable_base_index: 0,
+            reliable_window: VecDeque::new(),
+            packets: vec![],
             fragment_queue: FragmentQ::new(),
-            ordered_packets: HashMap::new(),
-            sequenced_frame_index: 0,
-            last_ordered_index: 0,
+            ordered_packets: vec![HashMap::new(); NUMBER_OF_ORDER_CHANNELS as usize],
+            window_bytes: 0,
+            max_window_bytes: RecvQ::DEFAULT_WINDOW_BYTES,
+            window_violations: 0,
+            sequenced_frame_index: [0; NUMBER_OF_ORDER_CHANNELS as usize],
+            last_ordered_index: [0; NUMBER_OF_ORDER_CHANNELS as usize],
         }
     }
 
     pub fn insert(&mut self, frame: FrameSetPacket) -> Result<()> {
-        if self.packets.contains_key(&frame.sequence_number) {
-            return Ok(());
+        if frame.is_ordered()? && frame.order_channel >= NUMBER_OF_ORDER_CHANNELS {
+            return Err(RaknetError::IncorrectOrderChannel);
         }
+        let channel = frame.order_channel as usize;
 
         self.sequence_number_ackset.insert(frame.sequence_number);
 
+        // a resent frame whose ack was lost , the frame has been received already
+        if frame.is_reliable()? && self.is_duplicate(frame.reliable_frame_index)? {
+            return Ok(());
+        }
+
+        if frame.is_fragment() {
+            // drop the fragments of packets that have been delivered already
+            if frame.reliability()?.without_ack_receipt() == Reliability::ReliableOrdered
+                && u24_lt(frame.ordered_frame_index, self.last_ordered_index[channel])
+            {
+                return Ok(());
+            }
+            if frame.is_sequenced()?
+                && u24_lt(
+                    frame.sequenced_frame_index,
+                    self.sequenced_frame_index[channel],
+                )
+            {
+                return Ok(());
+            }
+
+            self.fragment_queue.insert(frame)?;
+
+            for i in self.fragment_queue.flush()? {
+                self.insert_frame(i)?;
+            }
+            return Ok(());
+        }
+
+        self.insert_frame(frame)
+    }
+
+    /// Check that the frames of a datagram fit in the receive window before inserting them.
+    /// A datagram which does not fit is dropped and nacked , the peer resends it once the window has moved.
+    pub fn check_window(&mut self, datagram: &FrameVec) -> Result<()> {
+        let mut bytes = 0;
+        let mut ret = Ok(());
+
+        for frame in &datagram.frames {
+            if frame.is_reliable()?
+                && !u24_lt(frame.reliable_frame_index, self.reliable_base_index)
+                && u24_sub(frame.reliable_frame_index, self.reliable_base_index) as usize
+                    >= RecvQ::MAX_RELIABLE_WINDOW
+            {
+                ret = Err(RaknetError::ReliableWindowOverflow);
+                break;
+            }
+
+            let channel = frame.order_channel as usize;
+            if frame.is_ordered()?
+                && channel < NUMBER_OF_ORDER_CHANNELS as usize
+                && u24_gt(frame.ordered_frame_index, self.last_ordered_index[channel])
+            {
+                // the next ordered frame of a channel is always accepted , it lets the window drain
+                bytes += frame.size()?;
+                if self.window_bytes + bytes > self.max_window_bytes {
+                    ret = Err(RaknetError::ReceiveWindowFull);
+                    break;
+                }
+            }
+        }
+
+        if ret.is_err() {
+            self.sequence_number_ackset.nack(datagram.sequence_number);
+            self.window_violations += 1;
+            if self.window_violations > RecvQ::MAX_WINDOW_VIOLATIONS {
+                return Err(RaknetError::ReceiveWindowViolation);
+            }
+        }
+        ret
+    }
+
+    /// Whether the datagram has been received already , the network may duplicate datagrams.
+    pub fn is_duplicate_datagram(&self, sequence_number: u32) -> bool {
+        self.sequence_number_ackset.is_received(sequence_number)
+    }
+
+    /// Set the maximum of bytes of the ordered frames waiting for a missing one.
+    pub fn set_window(&mut self, max_bytes: usize) {
+        self.max_window_bytes = max_bytes;
+    }
+
+    fn is_duplicate(&mut self, reliable_frame_index: u32) -> Result<bool> {
+        if u24_lt(reliable_frame_index, self.reliable_base_index) {
+            return Ok(true);
+        }
+
+        let offset = u24_sub(reliable_frame_index, self.reliable_base_index) as usize;
+        if offset >= RecvQ::MAX_RELIABLE_WINDOW {
+            return Err(RaknetError::ReliableWindowOverflow);
+        }
+
+        if offset >= self.reliable_window.len() {
+            self.reliable_window.resize(offset + 1, false);
+        }
+        if self.reliable_window[offset] {
+            return Ok(true);
+        }
+        self.reliable_window[offset] = true;
+
+        while self.reliable_window.front() == Some(&true) {
+            self.reliable_window.pop_front();
+            self.reliable_base_index = u24_add(self.reliable_base_index, 1);
+        }
+        Ok(false)
+    }
+
+    fn insert_frame(&mut self, frame: FrameSetPacket) -> Result<()> {
+        let channel = frame.order_channel as usize;
+
         //The fourth parameter takes one of five major values. Lets say you send data 1,2,3,4,5,6. Here's the order and substance of what you might get back:
         match frame.reliability()? {
             // UNRELIABLE - 5, 1, 6
-            Reliability::Unreliable => {
-                self.packets.entry(frame.sequence_number).or_insert(frame);
+            Reliability::Unreliable | Reliability::UnreliableWithAckReceipt => {
+                self.packets.push(frame);
             }
             // UNRELIABLE_SEQUENCED - 5 (6 was lost in transit, 1,2,3,4 arrived later than 5)
             // With the UNRELIABLE_SEQUENCED transmission method, the game data does not need to arrive in every packet to avoid packet loss and retransmission,
             // because the new packet represents the new state, and the new state can be used directly, without waiting for the old packet to arrive.
             Reliability::UnreliableSequenced => {
                 let sequenced_frame_index = frame.sequenced_frame_index;
-                if sequenced_frame_index >= self.sequenced_frame_index {
-                    if let std::collections::hash_map::Entry::Vacant(e) =
-                        self.packets.entry(frame.sequence_number)
-                    {
-                        e.insert(frame);
-                        self.sequenced_frame_index = sequenced_frame_index + 1;
-                    }
+                if u24_ge(sequenced_frame_index, self.sequenced_frame_index[channel]) {
+                    self.packets.push(frame);
+                    self.sequenced_frame_index[channel] = u24_add(sequenced_frame_index, 1);
                 }
             }
             // RELIABLE - 5, 1, 4, 6, 2, 3
-            Reliability::Reliable => {
-                self.packets.insert(frame.sequence_number, frame);
+            Reliability::Reliable | Reliability::ReliableWithAckReceipt => {
+                self.packets.push(frame);
             }
             // RELIABLE_ORDERED - 1, 2, 3, 4, 5, 6
-            Reliability::ReliableOrdered => {
+            Reliability::ReliableOrdered | Reliability::ReliableOrderedWithAckReceipt => {
                 // if remote host not received ack , and local program has flush ordered packet. recvq will insert old packet caused memory leak.
-                if frame.ordered_frame_index < self.last_ordered_index {
+                if u24_lt(frame.ordered_frame_index, self.last_ordered_index[channel]) {
                     return Ok(());
                 }
 
-                if frame.is_fragment() {
-                    self.fragment_queue.insert(frame);
-
-                    for i in self.fragment_queue.flush()? {
-                        self.ordered_packets
-                            .entry(i.ordered_frame_index)
-                            .or_insert(i);
-                    }
-                } else {
-                    self.ordered_packets
-                        .entry(frame.ordered_frame_index)
-                        .or_insert(frame);
+                if let Entry::Vacant(e) =
+                    self.ordered_packets[channel].entry(frame.ordered_frame_index)
+                {
+                    self.window_bytes += frame.size()?;
+                    e.insert(frame);
                 }
             }
             // RELIABLE_SEQUENCED - 5, 6 (1,2,3,4 arrived later than 5)
             Reliability::ReliableSequenced => {
                 let sequenced_frame_index = frame.sequenced_frame_index;
-                if sequenced_frame_index >= self.sequenced_frame_index {
-                    if let std::collections::hash_map::Entry::Vacant(e) =
-                        self.packets.entry(frame.sequence_number)
-                    {
-                        e.insert(frame);
-                        self.sequenced_frame_index = sequenced_frame_index + 1;
-                    }
+                if u24_ge(sequenced_frame_index, self.sequenced_frame_index[channel]) {
+                    self.packets.push(frame);
+                    self.sequenced_frame_index[channel] = u24_add(sequenced_frame_index, 1);
                 }
             }
         }
@@ -526,47 +891,68 @@ impl RecvQ {
         self.sequence_number_ackset.get_ack()
     }
 
-    pub fn get_nack(&mut self) -> Vec<(u32, u32)> {
-        self.sequence_number_ackset.get_nack()
+    pub fn get_nack(&mut self, tick: i64) -> Vec<(u32, u32)> {
+        self.sequence_number_ackset.get_nack(tick)
+    }
+
+    pub fn set_reorder_threshold(&mut self, threshold: ReorderThreshold) {
+        self.sequence_number_ackset.set_reorder_threshold(threshold);
     }
 
     pub fn flush(&mut self, _peer_addr: &SocketAddr) -> Vec<FrameSetPacket> {
         let mut ret = vec![];
-        let mut ordered_keys: Vec<u32> = self.ordered_packets.keys().cloned().collect();
 
-        ordered_keys.sort_unstable();
-
-        for i in ordered_keys {
-            if i == self.last_ordered_index {
-                let frame = self.ordered_packets[&i].clone();
+        // every channel is ordered on its own , a gap in one channel does not block the others
+        for channel in 0..NUMBER_OF_ORDER_CHANNELS as usize {
+            let ordered_packets = &mut self.ordered_packets[channel];
+            while let Some(frame) = ordered_packets.remove(&self.last_ordered_index[channel]) {
+                self.window_bytes -= frame.size().unwrap();
                 ret.push(frame);
-                self.ordered_packets.remove(&i);
                 //raknet_log!("{} : received ordered [{}]" , peer_addr ,self.last_ordered_index);
-                self.last_ordered_index = i + 1;
+                self.last_ordered_index[channel] = u24_add(self.last_ordered_index[channel], 1);
             }
         }
 
-        let mut packets_keys: Vec<u32> = self.packets.keys().cloned().collect();
-        packets_keys.sort_unstable();
-
-        for i in packets_keys {
-            let v = self.packets.get(&i).unwrap();
-            ret.push(v.clone());
+        // a peer which lets the window drain is not flooding it
+        if self.window_bytes < self.max_window_bytes / 2 {
+            self.window_violations = 0;
         }
 
-        self.packets.clear();
+        ret.append(&mut self.packets);
         ret
     }
+
+    /// bytes of the frames waiting for a missing one , in the ordered queues and the fragment queue
+    pub fn get_window_bytes(&self) -> usize {
+        self.window_bytes + self.fragment_queue.get_total_bytes()
+    }
+
     pub fn get_ordered_packet(&self) -> usize {
-        self.ordered_packets.len()
+        self.ordered_packets.iter().map(|x| x.len()).sum()
     }
 
     pub fn get_fragment_queue_size(&self) -> usize {
         self.fragment_queue.size()
     }
 
+    pub fn set_fragment_limits(&mut self, limits: FragmentLimits) {
+        self.fragment_queue.set_limits(limits);
+    }
+
+    /// drop the expired packets of the fragment queue , fails if a reliable one expired
+    pub fn tick(&mut self, tick: i64) -> Result<()> {
+        let n = self.fragment_queue.expire(tick)?;
+        if n != 0 {
+            raknet_log_debug!("drop {} expired fragmented packets", n);
+        }
+        Ok(())
+    }
+
     pub fn get_ordered_keys(&self) -> Vec<u32> {
-        self.ordered_packets.keys().cloned().collect()
+        self.ordered_packets
+            .iter()
+            .flat_map(|x| x.keys().cloned())
+            .collect()
     }
 
     pub fn get_size(&self) -> usize {
@@ -576,211 +962,519 @@ impl RecvQ {
 
 pub struct SendQ {
     mtu: u16,
-    ack_sequence_number: u32,
+    // ip header + udp header
+    udp_overhead: usize,
+    // the newest acked sequence number , the datagrams sent before it may be lost
+    largest_acked_sequence_number: Option<u32>,
+    reorder_threshold: ReorderThreshold,
     sequence_number: u32,
     reliable_frame_index: u32,
-    sequenced_frame_index: u32,
-    ordered_frame_index: u32,
+    sequenced_frame_index: [u32; NUMBER_OF_ORDER_CHANNELS as usize],
+    ordered_frame_index: [u32; NUMBER_OF_ORDER_CHANNELS as usize],
     compound_id: u16,
-    //packet : FrameSetPacket , is_sent: bool ,last_tick : i64 , resend_times : u32
-    packets: Vec<FrameSetPacket>,
+    // weight , frame . frames with a lower weight are sent first
+    packets: Vec<(u64, FrameSetPacket)>,
+    next_weight: [u64; 4],
+    // weight of the last packed frame
+    last_weight: u64,
     rto: i64,
-    srtt: i64,
-    sent_packet: Vec<(FrameSetPacket, bool, i64, u32, Vec<u32>)>,
+    srtt: f64,
+    rttvar: f64,
+    min_rtt: Option<i64>,
+    rto_options: RtoOptions,
+    //datagram : FrameVec , is_sent: bool ,last_tick : i64 , resend_times : u32 , sequence numbers : Vec<u32>
+    sent_packet: Vec<(FrameVec, bool, i64, u32, Vec<u32>)>,
+    receipt_id: u32,
+    // receipt id -> (frames not acked yet , result sender)
+    receipts: HashMap<u32, (usize, oneshot::Sender<bool>)>,
+    // unreliable datagrams carrying receipts : sequence number , send tick , receipt ids
+    unreliable_receipts: Vec<(u32, i64, Vec<u32>)>,
+    max_window_datagrams: usize,
+    max_window_bytes: usize,
+    // payload bytes of the frames in packets
+    queued_bytes: usize,
+    max_buffer_bytes: usize,
+    congestion_controller: Box<dyn CongestionController>,
+    // bytes the pacing of the congestion controller allows to send
+    pacing_budget: usize,
+    last_pacing_tick: Option<i64>,
+    max_retransmissions: u32,
+    max_unacked_millis: i64,
+    // tick of the last ack which freed a datagram , None if nothing is waiting for an ack
+    last_ack_progress_tick: Option<i64>,
+    // the peer stopped acking , the connection is lost
+    lost: bool,
+    // datagrams sent again
+    retransmissions: u64,
 }
 
 impl SendQ {
     pub const DEFAULT_TIMEOUT_MILLS: i64 = 50;
 
-    const RTO_UBOUND: i64 = 12000;
-    const RTO_LBOUND: i64 = 50;
+    /// default maximum of unacked datagrams in flight
+    pub const DEFAULT_WINDOW_DATAGRAMS: usize = 512;
+    /// default maximum of unacked bytes in flight
+    pub const DEFAULT_WINDOW_BYTES: usize = 1024 * 1024;
+    /// default maximum of queued and unacked bytes
+    pub const DEFAULT_BUFFER_BYTES: usize = 8 * 1024 * 1024;
+    /// default maximum of resends of a datagram
+    pub const DEFAULT_MAX_RETRANSMISSIONS: u32 = 16;
+    /// default maximum of milliseconds without an ack while datagrams are waiting for one
+    pub const DEFAULT_MAX_UNACKED_MILLIS: i64 = 10000;
+
+    const INITIAL_WEIGHT: [u64; 4] = [0, 3, 10, 27];
 
     pub fn new(mtu: u16) -> Self {
+        let mut congestion_controller = Box::new(SlidingWindow::new());
+        congestion_controller.set_mtu(mtu);
+
         Self {
             mtu,
-            ack_sequence_number: 0,
+            // ipv4 header(20) + udp header(8)
+            udp_overhead: 28,
+            largest_acked_sequence_number: None,
+            reorder_threshold: ReorderThreshold::default(),
             sequence_number: 0,
             packets: vec![],
             sent_packet: vec![],
             reliable_frame_index: 0,
-            sequenced_frame_index: 0,
-            ordered_frame_index: 0,
+            sequenced_frame_index: [0; NUMBER_OF_ORDER_CHANNELS as usize],
+            ordered_frame_index: [0; NUMBER_OF_ORDER_CHANNELS as usize],
             compound_id: 0,
-
-            rto: SendQ::DEFAULT_TIMEOUT_MILLS,
-            srtt: SendQ::DEFAULT_TIMEOUT_MILLS,
+            next_weight: SendQ::INITIAL_WEIGHT,
+            last_weight: 0,
+
+            rto: RtoOptions::default().initial_rto,
+            srtt: 0.0,
+            rttvar: 0.0,
+            min_rtt: None,
+            rto_options: RtoOptions::default(),
+            max_window_datagrams: SendQ::DEFAULT_WINDOW_DATAGRAMS,
+            max_window_bytes: SendQ::DEFAULT_WINDOW_BYTES,
+            queued_bytes: 0,
+            max_buffer_bytes: SendQ::DEFAULT_BUFFER_BYTES,
+            receipt_id: 0,
+            receipts: HashMap::new(),
+            unreliable_receipts: vec![],
+            congestion_controller,
+            pacing_budget: 0,
+            last_pacing_tick: None,
+            max_retransmissions: SendQ::DEFAULT_MAX_RETRANSMISSIONS,
+            max_unacked_millis: SendQ::DEFAULT_MAX_UNACKED_MILLIS,
+            last_ack_progress_tick: None,
+            lost: false,
+            retransmissions: 0,
         }
     }
 
+    /// Replace the congestion controller , the default one is SlidingWindow.
+    pub fn set_congestion_controller(&mut self, mut controller: Box<dyn CongestionController>) {
+        controller.set_mtu(self.mtu);
+        self.congestion_controller = controller;
+        self.last_pacing_tick = None;
+    }
+
+    /// Set the maximum of unacked datagrams and bytes in flight , the congestion window can only be smaller.
+    /// New frames stay in the queue until acks free space in the window.
+    pub fn set_window(&mut self, max_datagrams: usize, max_bytes: usize) {
+        self.max_window_datagrams = max_datagrams.max(1);
+        self.max_window_bytes = max_bytes.max(self.mtu as usize);
+    }
+
+    /// Set the size of the ip and udp headers , 48 for ipv6 peers.
+    pub fn set_udp_overhead(&mut self, udp_overhead: usize) {
+        self.udp_overhead = udp_overhead;
+    }
+
+    pub fn get_mtu(&self) -> u16 {
+        self.mtu
+    }
+
+    /// Set when the peer is considered dead , see `is_lost`.
+    pub fn set_retransmission_limit(&mut self, max_retransmissions: u32, max_unacked_millis: i64) {
+        self.max_retransmissions = max_retransmissions;
+        self.max_unacked_millis = max_unacked_millis;
+    }
+
+    /// A datagram has been resent more than the maximum of retransmissions ,
+    /// or no datagram has been acked for the maximum of unacked milliseconds.
+    pub fn is_lost(&self) -> bool {
+        self.lost
+    }
+
+    /// Set the maximum of queued and unacked bytes , see `is_buffer_full`.
+    pub fn set_buffer(&mut self, max_bytes: usize) {
+        self.max_buffer_bytes = max_bytes;
+    }
+
+    /// No more packets should be queued until acks free space in the buffer.
+    /// A packet larger than the buffer is still accepted when the buffer is empty.
+    pub fn is_buffer_full(&self) -> bool {
+        self.queued_bytes + self.get_inflight_bytes() >= self.max_buffer_bytes
+    }
+
     pub fn insert(&mut self, reliability: Reliability, buf: &[u8]) -> Result<()> {
-        match reliability {
-            Reliability::Unreliable => {
-                // 60 = max framesetpacket length(27) + udp overhead(28) + 5 ext
-                if buf.len() > (self.mtu - 60).into() {
-                    return Err(RaknetError::PacketSizeExceedMTU);
-                }
+        self.insert_on_channel(reliability, buf, 0, Priority::Immediate)
+    }
 
-                let frame = FrameSetPacket::new(reliability, buf.to_vec());
-                self.packets.push(frame);
+    /// Queue a packet on one of the ordering channels.
+    /// Ordered and sequenced packets are only ordered against packets of the same channel.
+    pub fn insert_on_channel(
+        &mut self,
+        reliability: Reliability,
+        buf: &[u8],
+        order_channel: u8,
+        priority: Priority,
+    ) -> Result<()> {
+        if order_channel >= NUMBER_OF_ORDER_CHANNELS {
+            return Err(RaknetError::IncorrectOrderChannel);
+        }
+        let channel = order_channel as usize;
+        let reliability = reliability.without_ack_receipt();
+
+        // 32 = max framesetpacket length(27) + 5 ext
+        let max = self.mtu as usize - self.udp_overhead - 32;
+
+        // same as raknet , split packets must be reliable so unreliable ones are upgraded
+        let reliability = if buf.len() > max {
+            match reliability {
+                Reliability::Unreliable => Reliability::Reliable,
+                Reliability::UnreliableSequenced => Reliability::ReliableSequenced,
+                r => r,
             }
-            Reliability::UnreliableSequenced => {
-                // 60 = max framesetpacket length(27) + udp overhead(28) + 5 ext
-                if buf.len() > (self.mtu - 60).into() {
-                    return Err(RaknetError::PacketSizeExceedMTU);
-                }
+        } else {
+            reliability
+        };
 
-                let mut frame = FrameSetPacket::new(reliability, buf.to_vec());
-                // I dont know why Sequenced packet need Ordered
-                // https://wiki.vg/Raknet_Protocol
-                frame.ordered_frame_index = self.ordered_frame_index;
-                frame.sequenced_frame_index = self.sequenced_frame_index;
-                self.packets.push(frame);
-                self.sequenced_frame_index += 1;
+        // rounded up , an empty packet still takes one frame
+        let compound_size = buf.len().saturating_sub(1) / max + 1;
+        let weight = self.next_weight(priority);
+
+        for i in 0..compound_size {
+            let begin = max * i;
+            let end = buf.len().min(max * (i + 1));
+
+            let mut frame = FrameSetPacket::new(reliability.clone(), buf[begin..end].to_vec());
+
+            if compound_size > 1 {
+                // set fragment flag
+                frame.flags |= 16;
+                frame.compound_size = compound_size as u32;
+                frame.compound_id = self.compound_id;
+                frame.fragment_index = i as u32;
             }
-            Reliability::Reliable => {
-                // 60 = max framesetpacket length(27) + udp overhead(28) + 5 ext
-                if buf.len() > (self.mtu - 60).into() {
-                    return Err(RaknetError::PacketSizeExceedMTU);
-                }
 
-                let mut frame = FrameSetPacket::new(reliability, buf.to_vec());
+            // every fragment has its own reliable index , but they share the sequenced and ordered index
+            if frame.is_reliable()? {
                 frame.reliable_frame_index = self.reliable_frame_index;
-                self.packets.push(frame);
-                self.reliable_frame_index += 1;
+                self.reliable_frame_index = u24_add(self.reliable_frame_index, 1);
             }
-            Reliability::ReliableOrdered => {
-                // 60 = max framesetpacket length(27) + udp overhead(28) + 5 ext
-                if buf.len() < (self.mtu - 60).into() {
-                    let mut frame = FrameSetPacket::new(reliability, buf.to_vec());
-                    frame.reliable_frame_index = self.reliable_frame_index;
-                    frame.ordered_frame_index = self.ordered_frame_index;
-                    self.packets.push(frame);
-                    self.reliable_frame_index += 1;
-                    self.ordered_frame_index += 1;
-                } else {
-                    let max = (self.mtu - 60) as usize;
-                    let mut compound_size = buf.len() / max;
-                    if buf.len() % max != 0 {
-                        compound_size += 1;
-                    }
-
-                    for i in 0..compound_size {
-                        let begin = (max * i) as usize;
-                        let end = if i == compound_size - 1 {
-                            buf.len()
-                        } else {
-                            (max * (i + 1)) as usize
-                        };
-
-                        let mut frame =
-                            FrameSetPacket::new(reliability.clone(), buf[begin..end].to_vec());
-                        // set fragment flag
-                        frame.flags |= 16;
-                        frame.compound_size = compound_size as u32;
-                        frame.compound_id = self.compound_id;
-                        frame.fragment_index = i as u32;
-                        frame.reliable_frame_index = self.reliable_frame_index;
-                        frame.ordered_frame_index = self.ordered_frame_index;
-                        self.packets.push(frame);
-                        self.reliable_frame_index += 1;
-                    }
-                    self.compound_id += 1;
-                    self.ordered_frame_index += 1;
-                }
+            if frame.is_sequenced()? {
+                frame.sequenced_frame_index = self.sequenced_frame_index[channel];
             }
-            Reliability::ReliableSequenced => {
-                // 60 = max framesetpacket length(27) + udp overhead(28) + 5 ext
-                if buf.len() > (self.mtu - 60).into() {
-                    return Err(RaknetError::PacketSizeExceedMTU);
-                }
+            // I dont know why Sequenced packet need Ordered
+            // https://wiki.vg/Raknet_Protocol
+            if frame.is_ordered()? {
+                frame.ordered_frame_index = self.ordered_frame_index[channel];
+                frame.order_channel = order_channel;
+            }
+            self.packets.push((weight, frame));
+        }
+        self.queued_bytes += buf.len();
 
-                let mut frame = FrameSetPacket::new(reliability, buf.to_vec());
-                frame.reliable_frame_index = self.reliable_frame_index;
-                frame.sequenced_frame_index = self.sequenced_frame_index;
-                // I dont know why Sequenced packet need Ordered
-                // https://wiki.vg/Raknet_Protocol
-                frame.ordered_frame_index = self.ordered_frame_index;
-                self.packets.push(frame);
-                self.reliable_frame_index += 1;
-                self.sequenced_frame_index += 1;
+        if compound_size > 1 {
+            self.compound_id = self.compound_id.wrapping_add(1);
+        }
+
+        match reliability {
+            Reliability::UnreliableSequenced | Reliability::ReliableSequenced => {
+                self.sequenced_frame_index[channel] =
+                    u24_add(self.sequenced_frame_index[channel], 1);
             }
-        };
+            Reliability::ReliableOrdered => {
+                self.ordered_frame_index[channel] = u24_add(self.ordered_frame_index[channel], 1);
+            }
+            _ => {}
+        }
         Ok(())
     }
 
+    /// Queue a packet and return a receiver which gets true when every frame of it is acked.
+    /// It gets false when a datagram carrying an unreliable packet is lost.
+    pub fn insert_with_receipt(
+        &mut self,
+        reliability: Reliability,
+        buf: &[u8],
+        order_channel: u8,
+        priority: Priority,
+    ) -> Result<oneshot::Receiver<bool>> {
+        let begin = self.packets.len();
+        self.insert_on_channel(reliability, buf, order_channel, priority)?;
+
+        let receipt_id = self.receipt_id;
+        self.receipt_id = self.receipt_id.wrapping_add(1);
+
+        for (_, frame) in &mut self.packets[begin..] {
+            frame.receipt_id = Some(receipt_id);
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        self.receipts
+            .insert(receipt_id, (self.packets.len() - begin, sender));
+        Ok(receiver)
+    }
+
+    // every queued packet makes the next packet of the same priority heavier,
+    // so a lower priority is only delayed until the higher ones have caught up with its weight.
+    fn next_weight(&mut self, priority: Priority) -> u64 {
+        if self.packets.is_empty() {
+            self.next_weight = SendQ::INITIAL_WEIGHT;
+            self.last_weight = 0;
+        }
+
+        let p = priority as usize;
+        // a priority which has been idle does not jump ahead of the queued packets
+        let weight = self.next_weight[p].max(self.last_weight + priority.weight_step());
+        self.next_weight[p] = weight + priority.weight_step() + (1 << p);
+        weight
+    }
+
+    fn receipt_acked(&mut self, receipt_id: u32) {
+        if let Some(item) = self.receipts.get_mut(&receipt_id) {
+            item.0 -= 1;
+            if item.0 == 0 {
+                let (_, sender) = self.receipts.remove(&receipt_id).unwrap();
+                let _ = sender.send(true);
+            }
+        }
+    }
+
+    fn receipt_lost(&mut self, receipt_id: u32) {
+        if let Some((_, sender)) = self.receipts.remove(&receipt_id) {
+            let _ = sender.send(false);
+        }
+    }
+
+    // RFC 6298
     fn update_rto(&mut self, rtt: i64) {
-        // SRTT = ( ALPHA * SRTT ) + ((1-ALPHA) * RTT)
-        // ALPHA = 0.8
-        self.srtt = ((self.srtt as f64 * 0.8) + (rtt as f64 * 0.2)) as i64;
-        // RTO = min[UBOUND,max[LBOUND,(BETA*SRTT)]]
-        // BETA = 1.5
-        let rto_right = (1.5 * self.srtt as f64) as i64;
-        let rto_right = if rto_right > SendQ::RTO_LBOUND {
-            rto_right
-        } else {
-            SendQ::RTO_LBOUND
-        };
-        self.rto = if rto_right < SendQ::RTO_UBOUND {
-            rto_right
-        } else {
-            SendQ::RTO_UBOUND
-        };
+        let o = &self.rto_options;
+        let rtt = rtt as f64;
+
+        match self.min_rtt {
+            // SRTT = R , RTTVAR = R/2
+            None => {
+                self.srtt = rtt;
+                self.rttvar = rtt / 2.0;
+            }
+            // RTTVAR = (1 - beta) * RTTVAR + beta * |SRTT - R'|
+            // SRTT = (1 - alpha) * SRTT + alpha * R'
+            Some(_) => {
+                self.rttvar = (1.0 - o.beta) * self.rttvar + o.beta * (self.srtt - rtt).abs();
+                self.srtt = (1.0 - o.alpha) * self.srtt + o.alpha * rtt;
+            }
+        }
+        self.min_rtt = Some(self.min_rtt.map_or(rtt as i64, |x| x.min(rtt as i64)));
+
+        // RTO = SRTT + max (G, K*RTTVAR)
+        let rto = self.srtt + (o.granularity as f64).max(o.k * self.rttvar);
+        self.rto = (rto as i64).clamp(o.min_rto, o.max_rto);
+    }
+
+    /// Replace the constants of the retransmission timeout estimator.
+    pub fn set_rto_options(&mut self, options: RtoOptions) {
+        if self.min_rtt.is_none() {
+            self.rto = options.initial_rto;
+        }
+        self.rto_options = options;
     }
 
     pub fn get_rto(&self) -> i64 {
         self.rto
     }
 
+    /// smoothed round trip time , 0 before the first sample
+    pub fn get_srtt(&self) -> i64 {
+        self.srtt as i64
+    }
+
+    /// round trip time variance , 0 before the first sample
+    pub fn get_rttvar(&self) -> i64 {
+        self.rttvar as i64
+    }
+
+    /// minimum round trip time , 0 before the first sample
+    pub fn get_min_rtt(&self) -> i64 {
+        self.min_rtt.unwrap_or(0)
+    }
+
     pub fn nack(&mut self, sequence: u32, tick: i64) {
         for i in 0..self.sent_packet.len() {
             let item = &mut self.sent_packet[i];
             if item.1 && item.0.sequence_number == sequence {
                 raknet_log_debug!(
-                    "packet {}-{}-{} nack {} times",
+                    "datagram {} nack {} times",
                     item.0.sequence_number,
-                    item.0.reliable_frame_index,
-                    item.0.ordered_frame_index,
                     item.3 + 1
                 );
-                item.0.sequence_number = self.sequence_number;
-                self.sequence_number += 1;
+                // resend it at next flush
+                item.1 = false;
                 item.2 = tick;
-                item.3 += 1;
-                item.4.push(item.0.sequence_number);
+                self.congestion_controller.on_nack(tick);
             }
         }
-    }
 
-    pub fn ack(&mut self, sequence: u32, tick: i64) {
-        if sequence != 0 && sequence != self.ack_sequence_number + 1 {
-            for i in self.ack_sequence_number + 1..sequence {
-                self.nack(i, tick);
+        // unreliable frames are not resent , their receipts are lost
+        if let Some(i) = self
+            .unreliable_receipts
+            .iter()
+            .position(|x| x.0 == sequence)
+        {
+            for receipt_id in self.unreliable_receipts.remove(i).2 {
+                self.receipt_lost(receipt_id);
             }
         }
+    }
 
-        self.ack_sequence_number = sequence;
+    pub fn ack(&mut self, sequence: u32, tick: i64) {
+        // acks of a pipelined window may arrive out of order
+        match self.largest_acked_sequence_number {
+            Some(p) if !u24_gt(sequence, p) => {}
+            _ => self.largest_acked_sequence_number = Some(sequence),
+        }
 
         let mut rtts = vec![];
+        let mut receipts = vec![];
 
         for i in 0..self.sent_packet.len() {
             let item = &mut self.sent_packet[i];
             if item.0.sequence_number == sequence || item.4.contains(&sequence) {
-                rtts.push(tick - item.2);
-                self.sent_packet.remove(i);
+                // Karn's algorithm , the ack of a resent datagram may belong to any of its transmissions
+                if item.3 == 0 {
+                    rtts.push(tick - item.2);
+                }
+                let item = self.sent_packet.remove(i);
+                self.last_ack_progress_tick = Some(tick);
+                self.congestion_controller
+                    .on_ack(item.0.size().unwrap(), tick);
+                receipts.extend(item.0.frames.iter().filter_map(|x| x.receipt_id));
                 break;
             }
         }
 
+        if let Some(i) = self
+            .unreliable_receipts
+            .iter()
+            .position(|x| x.0 == sequence)
+        {
+            receipts.append(&mut self.unreliable_receipts.remove(i).2);
+        }
+
+        for i in receipts {
+            self.receipt_acked(i);
+        }
+
         for i in rtts {
+            self.congestion_controller.on_rtt(i);
             self.update_rto(i);
         }
+
+        self.detect_loss(tick);
     }
 
-    fn tick(&mut self, tick: i64) {
-        for i in 0..self.sent_packet.len() {
-            let p = &mut self.sent_packet[i];
+    /// Ack a range of sequence numbers received from the peer.
+    pub fn ack_range(&mut self, begin: u32, end: u32, tick: i64) {
+        if let Some((begin, end)) = self.clamp_range(begin, end) {
+            for i in u24_range(begin, end) {
+                self.ack(i, tick);
+            }
+        }
+    }
 
-            let mut cur_rto = self.rto;
+    /// Nack a range of sequence numbers received from the peer.
+    pub fn nack_range(&mut self, begin: u32, end: u32, tick: i64) {
+        if let Some((begin, end)) = self.clamp_range(begin, end) {
+            for i in u24_range(begin, end) {
+                self.nack(i, tick);
+            }
+        }
+    }
+
+    // the part of a range which may be in flight , from the oldest unacked transmission to the last sequence number sent.
+    // a forged range may cover half of the u24 space and every sequence number of it costs a scan of sent_packet
+    fn clamp_range(&self, begin: u32, end: u32) -> Option<(u32, u32)> {
+        let newest = u24_sub(self.sequence_number, 1);
+        let oldest = self
+            .sent_packet
+            .iter()
+            .flat_map(|x| x.4.iter())
+            .chain(self.unreliable_receipts.iter().map(|x| &x.0))
+            .max_by_key(|x| u24_sub(newest, **x))?;
+        let span = u24_sub(newest, *oldest);
+
+        let begin = if u24_lt(begin, *oldest) {
+            *oldest
+        } else {
+            begin
+        };
+        let end = if u24_gt(end, newest) { newest } else { end };
+        if u24_gt(begin, end) || u24_sub(begin, *oldest) > span || u24_sub(end, *oldest) > span {
+            return None;
+        }
+        Some((begin, end))
+    }
+
+    /// Set how much reordering is tolerated before a datagram sent before an acked one is resent.
+    pub fn set_reorder_threshold(&mut self, threshold: ReorderThreshold) {
+        self.reorder_threshold = threshold;
+    }
+
+    // like RACK , a datagram sent before an acked one is lost once it is overtaken by enough datagrams or waited long enough
+    fn detect_loss(&mut self, tick: i64) {
+        let largest = match self.largest_acked_sequence_number {
+            Some(p) => p,
+            None => return,
+        };
+        let packets = self.reorder_threshold.packets;
+        let timeout = self.srtt as i64 + self.reorder_threshold.millis;
+        let is_lost = |sequence: u32, sent_tick: i64| {
+            u24_lt(sequence, largest)
+                && (u24_sub(largest, sequence) >= packets || tick - sent_tick >= timeout)
+        };
+
+        let mut lost = false;
+        for p in &mut self.sent_packet {
+            if p.1 && is_lost(p.0.sequence_number, p.2) {
+                raknet_log_debug!("datagram {} lost , resend it", p.0.sequence_number);
+                p.1 = false;
+                p.2 = tick;
+                lost = true;
+            }
+        }
+        if lost {
+            self.congestion_controller.on_nack(tick);
+        }
+
+        // unreliable frames are not resent , their receipts are lost
+        let mut lost_receipts = vec![];
+        self.unreliable_receipts.retain(|x| {
+            if is_lost(x.0, x.1) {
+                lost_receipts.extend(x.2.iter().cloned());
+                return false;
+            }
+            true
+        });
+        for i in lost_receipts {
+            self.receipt_lost(i);
+        }
+    }
+
+    fn tick(&mut self, tick: i64) {
+        self.detect_loss(tick);
+
+        for i in 0..self.sent_packet.len() {
+            let p = &mut self.sent_packet[i];
+
+            let mut cur_rto = self.rto;
 
             // TCP timeout calculation is RTOx2, so three consecutive packet losses will make it RTOx8, which is very terrible,
             // while rust-raknet it is not x2, but x1.5 (Experimental results show that the value of 1.5 is relatively good), which has improved the transmission speed.
@@ -789,65 +1483,234 @@ impl SendQ {
             }
 
             if p.1 && tick - p.2 >= cur_rto {
-                p.0.sequence_number = self.sequence_number;
-                self.sequence_number += 1;
                 p.1 = false;
-                p.4.push(p.0.sequence_number);
+                self.congestion_controller.on_timeout(tick);
+            }
+        }
+
+        // dead peer detection
+        if self.sent_packet.is_empty() {
+            self.last_ack_progress_tick = None;
+        } else {
+            let since = *self.last_ack_progress_tick.get_or_insert(tick);
+            if tick - since > self.max_unacked_millis {
+                raknet_log_debug!("no ack for {}ms , connection lost", tick - since);
+                self.lost = true;
+            }
+        }
+        if let Some(p) = self
+            .sent_packet
+            .iter()
+            .find(|x| !x.1 && x.3 >= self.max_retransmissions)
+        {
+            raknet_log_debug!(
+                "datagram {} resent {} times , connection lost",
+                p.0.sequence_number,
+                p.3
+            );
+            self.lost = true;
+        }
+
+        // no ack for an unreliable datagram within rto , consider it lost
+        let rto = self.rto;
+        let mut lost = vec![];
+        self.unreliable_receipts.retain(|x| {
+            if tick - x.1 >= rto {
+                lost.extend(x.2.iter().cloned());
+                return false;
             }
+            true
+        });
+        for i in lost {
+            self.receipt_lost(i);
         }
     }
 
-    pub fn flush(&mut self, tick: i64, peer_addr: &SocketAddr) -> Vec<FrameSetPacket> {
+    /// Pack the queued frames into as few frame sets as the mtu allows, in order of priority.
+    /// Frames which do not fit in the send window stay in the queue.
+    fn pack(&mut self) -> Vec<FrameVec> {
+        let max = self.mtu as usize - self.udp_overhead;
+
+        // stable sort , frames of the same weight keep the order they were queued
+        self.packets.sort_by_key(|x| x.0);
+
+        let mut window_datagrams = self.sent_packet.len();
+        let mut window_bytes = self.get_inflight_bytes();
+        let max_window_bytes = self
+            .max_window_bytes
+            .min(self.congestion_controller.window().max(self.mtu as usize));
+        let paced = self.congestion_controller.pacing_rate().is_some();
+
+        let mut ret = vec![];
+        let mut datagram = FrameVec {
+            id: 0x80 | NEEDS_B_AND_AS_FLAG,
+            sequence_number: 0,
+            frames: vec![],
+        };
+        let mut datagram_size = FrameVec::HEADER_SIZE;
+        // size of the part of the datagram which will be tracked for resending
+        let mut reliable_size = 0;
+
+        let mut packets = std::mem::take(&mut self.packets).into_iter();
+
+        for (weight, frame) in packets.by_ref() {
+            let frame_size = frame.size().unwrap();
+            if datagram.frames.is_empty() || datagram_size + frame_size > max {
+                if !datagram.frames.is_empty() {
+                    self.pacing_budget = self.pacing_budget.saturating_sub(datagram_size);
+                    if reliable_size != 0 {
+                        window_datagrams += 1;
+                        window_bytes += FrameVec::HEADER_SIZE + reliable_size;
+                    }
+                    ret.push(std::mem::replace(
+                        &mut datagram,
+                        FrameVec {
+                            id: 0x80 | NEEDS_B_AND_AS_FLAG,
+                            sequence_number: 0,
+                            frames: vec![],
+                        },
+                    ));
+                    datagram_size = FrameVec::HEADER_SIZE;
+                    reliable_size = 0;
+                }
+
+                if window_datagrams >= self.max_window_datagrams
+                    || window_bytes >= max_window_bytes
+                    || (paced && self.pacing_budget == 0)
+                {
+                    // send window is full , wait for acks
+                    self.packets.push((weight, frame));
+                    break;
+                }
+            }
+            self.last_weight = weight;
+            datagram_size += frame_size;
+            if frame.is_reliable().unwrap() {
+                reliable_size += frame_size;
+            }
+            self.queued_bytes -= frame.data.len();
+            datagram.frames.push(frame);
+        }
+        self.packets.extend(packets);
+
+        if !datagram.frames.is_empty() {
+            self.pacing_budget = self.pacing_budget.saturating_sub(datagram_size);
+            ret.push(datagram);
+        }
+        ret
+    }
+
+    fn update_pacing_budget(&mut self, tick: i64) {
+        if let Some(rate) = self.congestion_controller.pacing_rate() {
+            // a paced connection bursts one window at most
+            let max = self.congestion_controller.window().max(self.mtu as usize);
+            self.pacing_budget = match self.last_pacing_tick {
+                Some(last_tick) => {
+                    let elapsed = (tick - last_tick).max(0) as usize;
+                    self.pacing_budget
+                        .saturating_add(rate.saturating_mul(elapsed) / 1000)
+                        .min(max)
+                }
+                None => max,
+            };
+        }
+        self.last_pacing_tick = Some(tick);
+    }
+
+    pub fn flush(&mut self, tick: i64, peer_addr: &SocketAddr) -> Vec<FrameVec> {
         self.tick(tick);
+        self.update_pacing_budget(tick);
 
         let mut ret = vec![];
 
+        // retransmissions go first , they already hold a place in the send window
         if !self.sent_packet.is_empty() {
+            // every sent sequence number is older than the next one
+            let base = self.sequence_number;
             self.sent_packet
-                .sort_by(|x, y| x.0.sequence_number.cmp(&y.0.sequence_number));
+                .sort_by_key(|x| u24_sub(x.0.sequence_number, base));
 
             for i in 0..self.sent_packet.len() {
                 let p = &mut self.sent_packet[i];
                 if !p.1 {
+                    p.0.sequence_number = self.sequence_number;
+                    self.sequence_number = u24_add(self.sequence_number, 1);
+                    p.4.push(p.0.sequence_number);
                     raknet_log_debug!(
-                        "{} , packet {}-{}-{} resend {} times",
+                        "{} , datagram {} resend {} times",
                         peer_addr,
                         p.0.sequence_number,
-                        p.0.reliable_frame_index,
-                        p.0.ordered_frame_index,
                         p.3 + 1
                     );
+                    self.pacing_budget = self.pacing_budget.saturating_sub(p.0.size().unwrap());
                     ret.push(p.0.clone());
                     p.1 = true;
                     p.2 = tick;
                     p.3 += 1;
+                    self.retransmissions += 1;
                 }
             }
-            return ret;
-        }
-
-        if !self.packets.is_empty() {
-            for i in 0..self.packets.len() {
-                self.packets[i].sequence_number = self.sequence_number;
-                self.sequence_number += 1;
-                ret.push(self.packets[i].clone());
-                if self.packets[i].is_reliable().unwrap() {
-                    self.sent_packet.push((
-                        self.packets[i].clone(),
-                        true,
-                        tick,
-                        0,
-                        vec![self.packets[i].sequence_number],
-                    ));
-                }
+        }
+
+        for mut datagram in self.pack() {
+            datagram.sequence_number = self.sequence_number;
+            self.sequence_number = u24_add(self.sequence_number, 1);
+
+            let unreliable_receipts: Vec<u32> = datagram
+                .frames
+                .iter()
+                .filter(|x| !x.is_reliable().unwrap())
+                .filter_map(|x| x.receipt_id)
+                .collect();
+            if !unreliable_receipts.is_empty() {
+                self.unreliable_receipts.push((
+                    datagram.sequence_number,
+                    tick,
+                    unreliable_receipts,
+                ));
             }
 
-            self.packets.clear();
+            // only reliable frames will be resent when the datagram is lost
+            let reliable_frames: Vec<FrameSetPacket> = datagram
+                .frames
+                .iter()
+                .filter(|x| x.is_reliable().unwrap())
+                .cloned()
+                .collect();
+
+            if !reliable_frames.is_empty() {
+                let reliable_datagram = FrameVec {
+                    id: datagram.id,
+                    sequence_number: datagram.sequence_number,
+                    frames: reliable_frames,
+                };
+                self.sent_packet.push((
+                    reliable_datagram,
+                    true,
+                    tick,
+                    0,
+                    vec![datagram.sequence_number],
+                ));
+                self.last_ack_progress_tick.get_or_insert(tick);
+            }
+            ret.push(datagram);
         }
 
         ret
     }
 
+    pub fn get_congestion_window(&self) -> usize {
+        self.congestion_controller.window()
+    }
+
+    pub fn get_inflight_bytes(&self) -> usize {
+        self.sent_packet.iter().map(|x| x.0.size().unwrap()).sum()
+    }
+
+    pub fn get_queued_bytes(&self) -> usize {
+        self.queued_bytes
+    }
+
     pub fn is_empty(&self) -> bool {
         self.packets.is_empty() && self.sent_packet.is_empty()
     }
@@ -859,6 +1722,10 @@ impl SendQ {
     pub fn get_sent_queue_size(&self) -> usize {
         self.sent_packet.len()
     }
+
+    pub fn get_retransmissions(&self) -> u64 {
+        self.retransmissions
+    }
 }
 
 #[tokio::test]
@@ -897,6 +1764,277 @@ async fn test_ackset() {
     assert!(acks == vec![(0, 0), (2, 2)]);
 }
 
+#[tokio::test]
+async fn test_ackset_wraparound() {
+    let mut ackset = ACKSet::new();
+    ackset.next = U24_MAX - 1;
+
+    ackset.insert(U24_MAX - 1);
+    ackset.insert(U24_MAX);
+    ackset.insert(0);
+    ackset.insert(1);
+
+    assert!(ackset.get_ack() == vec![(U24_MAX - 1, U24_MAX), (0, 1)]);
+    assert!(ackset.get_nack(0).is_empty());
+
+    // late datagram from before the wrap is not a gap
+    ackset.insert(U24_MAX - 1);
+    assert!(ackset.get_nack(0).is_empty());
+
+    let mut ackset = ACKSet::new();
+    ackset.next = U24_MAX - 1;
+    ackset.insert(U24_MAX - 1);
+    ackset.insert(2);
+    assert!(ackset.get_nack(cur_timestamp_millis() + 1000) == vec![(U24_MAX, U24_MAX), (0, 1)]);
+}
+
+#[tokio::test]
+async fn test_ackset_reorder() {
+    let mut ackset = ACKSet::new();
+
+    // a reordered datagram is not a loss
+    ackset.insert(0);
+    ackset.insert(2);
+    ackset.insert(1);
+    assert!(ackset.get_nack(0).is_empty());
+
+    ackset.insert(4);
+    ackset.insert(5);
+    assert!(ackset.get_nack(0).is_empty());
+    // overtaken by 3 datagrams
+    ackset.insert(6);
+    assert!(ackset.get_nack(0) == vec![(3, 3)]);
+
+    // missing for too long
+    ackset.insert(9);
+    assert!(ackset.get_nack(0).is_empty());
+    assert!(ackset.get_nack(cur_timestamp_millis() + 20) == vec![(7, 8)]);
+    assert!(ackset.get_nack(cur_timestamp_millis() + 20).is_empty());
+}
+
+#[tokio::test]
+async fn test_sendq_forged_ack_range() {
+    let mut s = SendQ::new(1400);
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    for _ in 0..3 {
+        s.insert(Reliability::Reliable, &[0xfe; 10]).unwrap();
+        assert!(s.flush(0, &sockaddr).len() == 1);
+    }
+
+    // only the datagrams in flight are walked
+    assert!(s.clamp_range(0, 0x7fffff) == Some((0, 2)));
+    assert!(s.clamp_range(0x800000, U24_MAX).is_none());
+    assert!(s.clamp_range(1, 1) == Some((1, 1)));
+    assert!(s.clamp_range(3, 0x7fffff).is_none());
+
+    // a full width nack resends the datagrams in flight once
+    s.nack_range(0x400000, 0x3fffff, 10);
+    s.nack_range(0, 0x7fffff, 10);
+    assert!(s.flush(20, &sockaddr).len() == 3);
+
+    s.ack_range(U24_MAX - 0x7fffff, U24_MAX, 30);
+    assert!(!s.is_empty());
+    s.ack_range(0, 0x7fffff, 30);
+    assert!(s.is_empty());
+    assert!(s.clamp_range(0, 0x7fffff).is_none());
+}
+
+#[tokio::test]
+async fn test_sendq_reorder() {
+    let mut s = SendQ::new(1400);
+    s.set_congestion_controller(Box::new(Cubic::new()));
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    for i in 0..6 {
+        s.insert(Reliability::Reliable, &[0xfe; 1000]).unwrap();
+        assert!(s.flush(i, &sockaddr).len() == 1);
+    }
+
+    // acks of datagrams sent after 0 , it may still be reordered
+    s.ack(1, 10);
+    s.ack(2, 10);
+    assert!(s.flush(10, &sockaddr).is_empty());
+
+    s.ack(3, 10);
+    let ret = s.flush(10, &sockaddr);
+    assert!(ret.len() == 1);
+    assert!(ret[0].sequence_number == 6);
+
+    // 4 waits longer than the round trip time
+    s.ack(5, 20);
+    assert!(s.flush(20, &sockaddr).is_empty());
+    let ret = s.flush(
+        4 + s.get_srtt() + ReorderThreshold::default().millis,
+        &sockaddr,
+    );
+    assert!(ret.len() == 1);
+    assert!(ret[0].sequence_number == 7);
+}
+
+#[tokio::test]
+async fn test_sendq_rto() {
+    let mut s = SendQ::new(1400);
+    s.set_congestion_controller(Box::new(Cubic::new()));
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+    assert!(s.get_rto() == RtoOptions::default().initial_rto);
+
+    s.insert(Reliability::Reliable, &[0xfe]).unwrap();
+    s.flush(0, &sockaddr);
+    s.ack(0, 100);
+    // SRTT = R , RTTVAR = R/2 , RTO = SRTT + 4 * RTTVAR
+    assert!(s.get_srtt() == 100 && s.get_rttvar() == 50);
+    assert!(s.get_rto() == 300);
+
+    s.insert(Reliability::Reliable, &[0xfe]).unwrap();
+    s.flush(100, &sockaddr);
+    s.ack(1, 180);
+    assert!(s.get_rttvar() == 42 && s.get_srtt() == 97);
+    assert!(s.get_rto() == 267);
+    assert!(s.get_min_rtt() == 80);
+
+    // the ack of a resent datagram is not sampled
+    s.insert(Reliability::Reliable, &[0xfe]).unwrap();
+    s.flush(200, &sockaddr);
+    let ret = s.flush(200 + 267, &sockaddr);
+    assert!(ret.len() == 1);
+    s.ack(ret[0].sequence_number, 470);
+    assert!(s.get_min_rtt() == 80);
+    assert!(s.get_rto() == 267);
+
+    s.set_rto_options(RtoOptions {
+        min_rto: 1000,
+        ..RtoOptions::default()
+    });
+    s.insert(Reliability::Reliable, &[0xfe]).unwrap();
+    let ret = s.flush(500, &sockaddr);
+    s.ack(ret[0].sequence_number, 580);
+    assert!(s.get_rto() == 1000);
+}
+
+#[tokio::test]
+async fn test_sendq_retransmission_limit() {
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    // resent twice , lost when the second resend times out
+    let mut s = SendQ::new(1400);
+    s.set_retransmission_limit(2, 100000);
+    s.insert(Reliability::Reliable, &[0xfe]).unwrap();
+    s.flush(0, &sockaddr);
+    assert!(s.flush(1000, &sockaddr).len() == 1);
+    assert!(s.flush(2500, &sockaddr).len() == 1);
+    assert!(!s.is_lost());
+    s.flush(4750, &sockaddr);
+    assert!(s.is_lost());
+
+    // acks keep the connection alive , silence does not
+    let mut s = SendQ::new(1400);
+    s.set_retransmission_limit(16, 500);
+    s.insert(Reliability::Reliable, &[0xfe]).unwrap();
+    s.flush(0, &sockaddr);
+    s.insert(Reliability::Reliable, &[0xfe]).unwrap();
+    s.flush(400, &sockaddr);
+    s.ack(0, 450);
+    s.flush(900, &sockaddr);
+    assert!(!s.is_lost());
+    s.flush(951, &sockaddr);
+    assert!(s.is_lost());
+
+    // nothing waiting for an ack
+    let mut s = SendQ::new(1400);
+    s.set_retransmission_limit(16, 500);
+    s.insert(Reliability::Reliable, &[0xfe]).unwrap();
+    s.flush(0, &sockaddr);
+    s.ack(0, 100);
+    s.flush(10000, &sockaddr);
+    assert!(!s.is_lost());
+}
+
+#[tokio::test]
+async fn test_session_wraparound() {
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+    let mut s = SendQ::new(1400);
+    let mut r = RecvQ::new();
+
+    // a long lived session just before the u24 counters wrap
+    let start = U24_MAX - 10;
+    s.sequence_number = start;
+    s.reliable_frame_index = start;
+    s.ordered_frame_index[0] = start;
+    s.sequenced_frame_index[1] = start;
+    r.sequence_number_ackset.next = start;
+    r.reliable_base_index = start;
+    r.last_ordered_index[0] = start;
+    r.sequenced_frame_index[1] = start;
+
+    let mut received = vec![];
+    for i in 0..30u8 {
+        s.insert(Reliability::ReliableOrdered, &[0xfe, i]).unwrap();
+        s.insert_on_channel(
+            Reliability::ReliableSequenced,
+            &[0xfe, i],
+            1,
+            Priority::Immediate,
+        )
+        .unwrap();
+
+        let tick = i as i64 * 10;
+        for datagram in s.flush(tick, &sockaddr) {
+            // lose one datagram on each side of the wrap
+            if i == 5 || i == 15 {
+                continue;
+            }
+            let v = FrameVec::new(datagram._serialize().unwrap()).unwrap();
+            for frame in v.frames {
+                r.insert(frame).unwrap();
+            }
+        }
+        for (begin, end) in r.get_ack() {
+            for seq in u24_range(begin, end) {
+                s.ack(seq, tick);
+            }
+        }
+        for (begin, end) in r.get_nack(tick) {
+            for seq in u24_range(begin, end) {
+                s.nack(seq, tick);
+            }
+        }
+        received.append(&mut r.flush(&sockaddr));
+    }
+    // resend the last lost datagram
+    for datagram in s.flush(1000, &sockaddr) {
+        let v = FrameVec::new(datagram._serialize().unwrap()).unwrap();
+        for frame in v.frames {
+            r.insert(frame).unwrap();
+        }
+    }
+    for (begin, end) in r.get_ack() {
+        for seq in u24_range(begin, end) {
+            s.ack(seq, 1000);
+        }
+    }
+    received.append(&mut r.flush(&sockaddr));
+
+    assert!(s.is_empty());
+    assert!(s.sequence_number < start);
+
+    let ordered: Vec<u8> = received
+        .iter()
+        .filter(|x| x.order_channel == 0)
+        .map(|x| x.data[1])
+        .collect();
+    assert!(ordered == (0..30).collect::<Vec<u8>>());
+
+    // sequenced packets are never delivered older than the newest one
+    let sequenced: Vec<u8> = received
+        .iter()
+        .filter(|x| x.order_channel == 1)
+        .map(|x| x.data[1])
+        .collect();
+    assert!(sequenced.len() >= 28);
+    assert!(sequenced.windows(2).all(|x| x[0] < x[1]));
+}
+
 #[tokio::test]
 async fn test_frame_serialize_deserialize() {
     //minecraft 1.18.12 first frame packet
@@ -920,6 +2058,7 @@ async fn test_recvq() {
 
     let mut p = FrameSetPacket::new(Reliability::Reliable, vec![]);
     p.sequence_number = 1;
+    p.reliable_frame_index = 1;
     p.ordered_frame_index = 1;
     r.insert(p).unwrap();
 
@@ -936,25 +2075,27 @@ async fn test_recvq_fragment() {
     p.ordered_frame_index = 0;
     p.compound_id = 1;
     p.compound_size = 3;
-    p.fragment_index = 1;
+    p.fragment_index = 0;
     r.insert(p).unwrap();
 
     let mut p = FrameSetPacket::new(Reliability::ReliableOrdered, vec![2]);
     p.flags |= 16;
     p.sequence_number = 1;
+    p.reliable_frame_index = 1;
     p.ordered_frame_index = 1;
     p.compound_id = 1;
     p.compound_size = 3;
-    p.fragment_index = 2;
+    p.fragment_index = 1;
     r.insert(p).unwrap();
 
     let mut p = FrameSetPacket::new(Reliability::ReliableOrdered, vec![3]);
     p.flags |= 16;
     p.sequence_number = 2;
+    p.reliable_frame_index = 2;
     p.ordered_frame_index = 2;
     p.compound_id = 1;
     p.compound_size = 3;
-    p.fragment_index = 3;
+    p.fragment_index = 2;
     r.insert(p).unwrap();
 
     let ret = r.flush(&"0.0.0.0:0".parse().unwrap());
@@ -962,6 +2103,540 @@ async fn test_recvq_fragment() {
     assert!(ret[0].data == vec![1, 2, 3]);
 }
 
+#[tokio::test]
+async fn test_recvq_fragment_limits() {
+    let mut r = RecvQ::new();
+    r.set_fragment_limits(FragmentLimits {
+        max_compound_size: 4,
+        max_total_bytes: 10,
+        max_compounds: 2,
+        timeout: 1000,
+    });
+
+    let fragment = |compound_id: u16, compound_size: u32, fragment_index: u32, len: usize| {
+        let mut p = FrameSetPacket::new(Reliability::Unreliable, vec![0xfe; len]);
+        p.flags |= 16;
+        p.compound_id = compound_id;
+        p.compound_size = compound_size;
+        p.fragment_index = fragment_index;
+        p
+    };
+
+    assert!(matches!(
+        r.insert(fragment(0, 5, 0, 1)),
+        Err(RaknetError::CompoundTooLarge)
+    ));
+    assert!(matches!(
+        r.insert(fragment(0, 2, 2, 1)),
+        Err(RaknetError::PacketHeaderError)
+    ));
+
+    r.insert(fragment(0, 2, 0, 4)).unwrap();
+    r.insert(fragment(1, 2, 0, 4)).unwrap();
+    assert!(matches!(
+        r.insert(fragment(2, 2, 0, 1)),
+        Err(RaknetError::TooManyCompounds)
+    ));
+    assert!(matches!(
+        r.insert(fragment(0, 2, 1, 4)),
+        Err(RaknetError::FragmentBufferFull)
+    ));
+
+    // a reassembled packet frees its bytes
+    r.insert(fragment(0, 2, 1, 2)).unwrap();
+    assert!(r.flush(&"0.0.0.0:0".parse().unwrap()).len() == 1);
+    assert!(r.get_fragment_queue_size() == 1);
+    r.insert(fragment(2, 2, 0, 6)).unwrap();
+
+    // stale packets expire
+    r.tick(cur_timestamp_millis() + 1001).unwrap();
+    assert!(r.get_fragment_queue_size() == 0);
+    r.insert(fragment(3, 2, 0, 10)).unwrap();
+}
+
+#[tokio::test]
+async fn test_recvq_fragment_expire() {
+    let mut r = RecvQ::new();
+    r.set_fragment_limits(FragmentLimits {
+        timeout: 1000,
+        ..FragmentLimits::default()
+    });
+
+    // one fragment of the first ordered packet , then the next packet of the channel
+    let mut p = FrameSetPacket::new(Reliability::ReliableOrdered, vec![1]);
+    p.flags |= 16;
+    p.ordered_frame_index = 0;
+    p.compound_id = 1;
+    p.compound_size = 2;
+    p.fragment_index = 0;
+    r.insert(p).unwrap();
+
+    let mut p = FrameSetPacket::new(Reliability::ReliableOrdered, vec![2]);
+    p.sequence_number = 1;
+    p.reliable_frame_index = 1;
+    p.ordered_frame_index = 1;
+    r.insert(p).unwrap();
+
+    assert!(r.flush(&"0.0.0.0:0".parse().unwrap()).is_empty());
+    r.tick(cur_timestamp_millis()).unwrap();
+
+    // the channel waits for a packet which will never be complete
+    assert!(matches!(
+        r.tick(cur_timestamp_millis() + 1001),
+        Err(RaknetError::CompoundExpired)
+    ));
+    assert!(r.get_fragment_queue_size() == 0);
+    assert!(r.flush(&"0.0.0.0:0".parse().unwrap()).is_empty());
+    assert!(r.get_ordered_packet() == 1);
+}
+
+#[tokio::test]
+async fn test_recvq_order_channel() {
+    let mut r = RecvQ::new();
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    // ordered index 0 of channel 0 is lost
+    let mut p = FrameSetPacket::new(Reliability::ReliableOrdered, vec![0xfe, 1]);
+    p.sequence_number = 1;
+    p.reliable_frame_index = 1;
+    p.ordered_frame_index = 1;
+    r.insert(p).unwrap();
+
+    // channel 1 is not blocked by channel 0
+    let mut p = FrameSetPacket::new(Reliability::ReliableOrdered, vec![0xfe, 2]);
+    p.sequence_number = 2;
+    p.reliable_frame_index = 2;
+    p.ordered_frame_index = 0;
+    p.order_channel = 1;
+    r.insert(p).unwrap();
+
+    let ret = r.flush(&sockaddr);
+    assert!(ret.len() == 1);
+    assert!(ret[0].data == vec![0xfe, 2]);
+    assert!(ret[0].order_channel == 1);
+
+    let mut p = FrameSetPacket::new(Reliability::ReliableOrdered, vec![0xfe, 0]);
+    p.sequence_number = 0;
+    p.ordered_frame_index = 0;
+    r.insert(p).unwrap();
+
+    let ret = r.flush(&sockaddr);
+    assert!(ret.len() == 2);
+    assert!(ret[0].data == vec![0xfe, 0]);
+    assert!(ret[1].data == vec![0xfe, 1]);
+
+    // sequenced packets are sequenced per channel too
+    let mut p = FrameSetPacket::new(Reliability::UnreliableSequenced, vec![0xfe, 3]);
+    p.sequenced_frame_index = 5;
+    p.order_channel = 2;
+    r.insert(p).unwrap();
+
+    let mut p = FrameSetPacket::new(Reliability::UnreliableSequenced, vec![0xfe, 4]);
+    p.sequenced_frame_index = 0;
+    p.order_channel = 3;
+    r.insert(p).unwrap();
+
+    assert!(r.flush(&sockaddr).len() == 2);
+
+    let mut p = FrameSetPacket::new(Reliability::ReliableOrdered, vec![0xfe]);
+    p.order_channel = NUMBER_OF_ORDER_CHANNELS;
+    assert!(r.insert(p).is_err());
+}
+
+#[tokio::test]
+async fn test_recvq_duplicate() {
+    let mut r = RecvQ::new();
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    // the ack of the first datagram was lost , the sender resends the frames with new sequence numbers
+    for sequence_number in 0..2 {
+        let mut p = FrameSetPacket::new(Reliability::Reliable, vec![0xfe, 0]);
+        p.sequence_number = sequence_number * 2;
+        p.reliable_frame_index = 0;
+        r.insert(p).unwrap();
+
+        let mut p = FrameSetPacket::new(Reliability::ReliableSequenced, vec![0xfe, 1]);
+        p.sequence_number = sequence_number * 2 + 1;
+        p.reliable_frame_index = 1;
+        p.sequenced_frame_index = 0;
+        r.insert(p).unwrap();
+    }
+
+    let ret = r.flush(&sockaddr);
+    assert!(ret.len() == 2);
+    assert!(ret[0].data == vec![0xfe, 0]);
+    assert!(ret[1].data == vec![0xfe, 1]);
+
+    // a hole keeps the indices after it in the window
+    let mut p = FrameSetPacket::new(Reliability::Reliable, vec![0xfe, 3]);
+    p.sequence_number = 4;
+    p.reliable_frame_index = 3;
+    r.insert(p).unwrap();
+    let mut p = FrameSetPacket::new(Reliability::Reliable, vec![0xfe, 3]);
+    p.sequence_number = 5;
+    p.reliable_frame_index = 3;
+    r.insert(p).unwrap();
+    assert!(r.flush(&sockaddr).len() == 1);
+
+    let mut p = FrameSetPacket::new(Reliability::Reliable, vec![0xfe, 2]);
+    p.sequence_number = 6;
+    p.reliable_frame_index = 2;
+    r.insert(p).unwrap();
+    assert!(r.flush(&sockaddr).len() == 1);
+    assert!(r.reliable_base_index == 4);
+    assert!(r.reliable_window.is_empty());
+
+    // the window is bounded
+    let mut p = FrameSetPacket::new(Reliability::Reliable, vec![0xfe]);
+    p.sequence_number = 7;
+    p.reliable_frame_index = 4 + RecvQ::MAX_RELIABLE_WINDOW as u32;
+    assert!(r.insert(p).is_err());
+}
+
+#[tokio::test]
+async fn test_recvq_duplicate_datagram() {
+    let mut r = RecvQ::new();
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    let datagram = |sequence_number: u32| {
+        let mut p = FrameSetPacket::new(Reliability::Unreliable, vec![0xfe, 0]);
+        p.sequence_number = sequence_number;
+        FrameVec {
+            id: 0x84,
+            sequence_number,
+            frames: vec![p.clone(), p],
+        }
+    };
+
+    // the network duplicated datagram 1 , every frame of it is delivered once
+    let mut delivered = 0;
+    for sequence_number in [0, 2, 1, 1, 2] {
+        if r.is_duplicate_datagram(sequence_number) {
+            continue;
+        }
+        for frame in datagram(sequence_number).frames {
+            r.insert(frame).unwrap();
+        }
+        delivered += r.flush(&sockaddr).len();
+    }
+    assert!(delivered == 6);
+
+    // a nacked datagram arriving late is not a duplicate
+    r.insert(datagram(5).frames[0].clone()).unwrap();
+    assert!(r.get_nack(cur_timestamp_millis() + 1000) == vec![(3, 4)]);
+    assert!(!r.is_duplicate_datagram(4));
+    r.insert(datagram(4).frames[0].clone()).unwrap();
+    assert!(r.is_duplicate_datagram(4));
+    assert!(!r.is_duplicate_datagram(3));
+    assert!(!r.is_duplicate_datagram(6));
+}
+
+#[tokio::test]
+async fn test_recvq_window() {
+    let mut r = RecvQ::new();
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    let datagram = |sequence_number: u32, ordered_frame_index: u32| {
+        let mut p = FrameSetPacket::new(Reliability::ReliableOrdered, vec![0xfe; 40]);
+        p.sequence_number = sequence_number;
+        p.reliable_frame_index = ordered_frame_index;
+        p.ordered_frame_index = ordered_frame_index;
+        FrameVec {
+            id: 0x84,
+            sequence_number,
+            frames: vec![p],
+        }
+    };
+    let frame_size = datagram(0, 0).frames[0].size().unwrap();
+    r.set_window(frame_size * 2);
+
+    // ordered index 0 is lost , the frames after it wait in the window
+    for i in 1..3 {
+        let d = datagram(i, i);
+        r.check_window(&d).unwrap();
+        for frame in d.frames {
+            r.insert(frame).unwrap();
+        }
+    }
+    assert!(r.flush(&sockaddr).is_empty());
+    assert!(r.get_window_bytes() == frame_size * 2);
+
+    // the window is full , the datagram is dropped and nacked
+    assert!(matches!(
+        r.check_window(&datagram(3, 3)),
+        Err(RaknetError::ReceiveWindowFull)
+    ));
+    assert!(r.get_nack(0) == vec![(3, 3), (0, 0)]);
+
+    // the missing frame is always accepted
+    let d = datagram(4, 0);
+    r.check_window(&d).unwrap();
+    for frame in d.frames {
+        r.insert(frame).unwrap();
+    }
+    assert!(r.flush(&sockaddr).len() == 3);
+    assert!(r.get_window_bytes() == 0);
+
+    // a peer flooding the window is disconnected
+    r.set_window(0);
+    for i in 0..RecvQ::MAX_WINDOW_VIOLATIONS {
+        assert!(matches!(
+            r.check_window(&datagram(5 + i, 10)),
+            Err(RaknetError::ReceiveWindowFull)
+        ));
+    }
+    assert!(matches!(
+        r.check_window(&datagram(5 + RecvQ::MAX_WINDOW_VIOLATIONS, 10)),
+        Err(RaknetError::ReceiveWindowViolation)
+    ));
+}
+
+#[tokio::test]
+async fn test_sendq_order_channel() {
+    let mut s = SendQ::new(1400);
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    s.insert_on_channel(
+        Reliability::ReliableOrdered,
+        &[0xfe],
+        0,
+        Priority::Immediate,
+    )
+    .unwrap();
+    s.insert_on_channel(
+        Reliability::ReliableOrdered,
+        &[0xfe],
+        5,
+        Priority::Immediate,
+    )
+    .unwrap();
+    s.insert_on_channel(
+        Reliability::ReliableOrdered,
+        &[0xfe],
+        5,
+        Priority::Immediate,
+    )
+    .unwrap();
+    assert!(s
+        .insert_on_channel(
+            Reliability::ReliableOrdered,
+            &[0xfe],
+            NUMBER_OF_ORDER_CHANNELS,
+            Priority::Immediate
+        )
+        .is_err());
+
+    let ret = s.flush(0, &sockaddr);
+    let frames = &ret[0].frames;
+    assert!(frames.len() == 3);
+    assert!(frames[0].order_channel == 0 && frames[0].ordered_frame_index == 0);
+    assert!(frames[1].order_channel == 5 && frames[1].ordered_frame_index == 0);
+    assert!(frames[2].order_channel == 5 && frames[2].ordered_frame_index == 1);
+    assert!(frames[2].reliable_frame_index == 2);
+}
+
+#[tokio::test]
+async fn test_fragment_every_reliability() {
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    for r in [
+        Reliability::Unreliable,
+        Reliability::UnreliableSequenced,
+        Reliability::Reliable,
+        Reliability::ReliableOrdered,
+        Reliability::ReliableSequenced,
+    ] {
+        let mut s = SendQ::new(1400);
+        // room for every fragment in the first flush
+        s.set_congestion_controller(Box::new(Cubic::new()));
+        let mut recvq = RecvQ::new();
+        let buf: Vec<u8> = (0..4000).map(|x| x as u8).collect();
+
+        s.insert_on_channel(r.clone(), &buf, 3, Priority::Immediate)
+            .unwrap();
+
+        let mut datagrams = s.flush(0, &sockaddr);
+        assert!(datagrams.len() == 3);
+        // fragments arrive out of order
+        datagrams.reverse();
+
+        let mut ret = vec![];
+        for datagram in datagrams {
+            let v = FrameVec::new(datagram._serialize().unwrap()).unwrap();
+            for frame in v.frames {
+                assert!(frame.is_fragment());
+                // split packets are always reliable
+                assert!(frame.is_reliable().unwrap());
+                recvq.insert(frame).unwrap();
+                ret.append(&mut recvq.flush(&sockaddr));
+            }
+        }
+
+        assert!(ret.len() == 1);
+        assert!(ret[0].data == buf);
+        assert!(ret[0].is_reliable().unwrap());
+        assert!(
+            ret[0].is_sequenced().unwrap()
+                == (r == Reliability::UnreliableSequenced || r == Reliability::ReliableSequenced)
+        );
+        assert!(recvq.get_fragment_queue_size() == 0);
+    }
+}
+
+#[tokio::test]
+async fn test_sendq_ack_receipt() {
+    let mut s = SendQ::new(1400);
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    // 2 fragments in 2 datagrams
+    let mut reliable = s
+        .insert_with_receipt(
+            Reliability::ReliableWithAckReceipt,
+            &[0xfe; 2000],
+            0,
+            Priority::Immediate,
+        )
+        .unwrap();
+    let mut unreliable = s
+        .insert_with_receipt(
+            Reliability::UnreliableWithAckReceipt,
+            &[0xfe],
+            0,
+            Priority::Immediate,
+        )
+        .unwrap();
+
+    let ret = s.flush(0, &sockaddr);
+    assert!(ret.len() == 2);
+    // receipts are not sent on the wire
+    let v = FrameVec::new(ret[1]._serialize().unwrap()).unwrap();
+    assert!(v.frames[1].reliability().unwrap() == Reliability::Unreliable);
+
+    s.ack(0, 10);
+    assert!(reliable.try_recv().is_err());
+
+    // the unreliable frame shares datagram 1 with the second fragment
+    s.nack(1, 10);
+    assert!(!unreliable.try_recv().unwrap());
+
+    let ret = s.flush(20, &sockaddr);
+    assert!(ret.len() == 1);
+    assert!(ret[0].frames.len() == 1);
+    s.ack(2, 30);
+    assert!(reliable.try_recv().unwrap());
+
+    let mut unreliable = s
+        .insert_with_receipt(
+            Reliability::UnreliableWithAckReceipt,
+            &[0xfe],
+            0,
+            Priority::Immediate,
+        )
+        .unwrap();
+    s.flush(40, &sockaddr);
+    s.ack(3, 50);
+    assert!(unreliable.try_recv().unwrap());
+}
+
+#[tokio::test]
+async fn test_sendq_priority() {
+    let mut s = SendQ::new(1400);
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    s.insert_on_channel(
+        Reliability::ReliableOrdered,
+        &[0xfe; 5000],
+        1,
+        Priority::Low,
+    )
+    .unwrap();
+    s.insert_on_channel(Reliability::ReliableOrdered, &[0xfe, 1], 0, Priority::High)
+        .unwrap();
+    s.insert_on_channel(
+        Reliability::ReliableOrdered,
+        &[0xfe, 0],
+        0,
+        Priority::Immediate,
+    )
+    .unwrap();
+
+    let ret = s.flush(0, &sockaddr);
+    // the small packets do not wait for the big one
+    assert!(ret[0].frames[0].data == vec![0xfe, 0]);
+    assert!(ret[0].frames[1].data == vec![0xfe, 1]);
+    assert!(ret[0].frames.len() == 2);
+    assert!(ret[1].frames[0].is_fragment());
+
+    // low priority is not starved by a stream of high priority packets
+    let mut s = SendQ::new(1400);
+    s.insert(Reliability::Reliable, &[0xfe, 0]).unwrap();
+    s.insert_on_channel(Reliability::Reliable, &[0xfe, 1], 0, Priority::Low)
+        .unwrap();
+    for _ in 0..100 {
+        s.insert_on_channel(Reliability::Reliable, &[0xfe, 2], 0, Priority::High)
+            .unwrap();
+    }
+    let ret = s.flush(0, &sockaddr);
+    let frames: Vec<&FrameSetPacket> = ret.iter().flat_map(|x| x.frames.iter()).collect();
+    let low = frames.iter().position(|x| x.data[1] == 1).unwrap();
+    assert!(frames[0].data[1] == 0);
+    assert!(low > 1 && low < 20);
+}
+
+#[tokio::test]
+async fn test_sendq_congestion_window() {
+    let mut s = SendQ::new(1400);
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    for _ in 0..20 {
+        s.insert(Reliability::Reliable, &[0xfe; 1000]).unwrap();
+    }
+
+    // slow start begins with a window of one mtu
+    assert!(s.get_congestion_window() == 1400);
+    let ret = s.flush(0, &sockaddr);
+    assert!(ret.len() == 2);
+
+    s.ack(0, 10);
+    s.ack(1, 10);
+    let window = s.get_congestion_window();
+    assert!(window > 1400);
+    let ret = s.flush(10, &sockaddr);
+    assert!(ret.len() > 2);
+
+    s.nack(ret[0].sequence_number, 100);
+    assert!(s.get_congestion_window() < window);
+
+    s.set_congestion_controller(Box::new(Cubic::new()));
+    assert!(s.get_congestion_window() == 1400 * 4);
+}
+
+#[tokio::test]
+async fn test_sendq_buffer() {
+    let mut s = SendQ::new(1400);
+    s.set_congestion_controller(Box::new(Cubic::new()));
+    s.set_buffer(3000);
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    s.insert(Reliability::Reliable, &[0xfe; 1000]).unwrap();
+    s.insert(Reliability::Unreliable, &[0xfe; 1000]).unwrap();
+    assert!(s.get_queued_bytes() == 2000);
+    assert!(!s.is_buffer_full());
+    s.insert(Reliability::Reliable, &[0xfe; 1000]).unwrap();
+    assert!(s.is_buffer_full());
+
+    // sent reliable frames stay in the buffer until they are acked
+    let ret = s.flush(0, &sockaddr);
+    assert!(s.get_queued_bytes() == 0);
+    assert!(s.get_inflight_bytes() > 2000);
+    assert!(!s.is_buffer_full());
+
+    for i in ret {
+        s.ack(i.sequence_number, 10);
+    }
+    assert!(s.get_inflight_bytes() == 0);
+}
+
 #[tokio::test]
 async fn test_sendq() {
     let mut s = SendQ::new(1500);
@@ -975,15 +2650,67 @@ async fn test_sendq() {
 
     let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
     let ret = s.flush(0, &sockaddr);
-    assert!(ret.len() == 2);
+    assert!(ret.len() == 1);
+    assert!(ret[0].frames.len() == 2);
 
     s.ack(0, 0);
-    s.ack(1, 0);
 
     let ret = s.flush(300, &sockaddr);
     assert!(ret.is_empty());
 }
 
+#[tokio::test]
+async fn test_sendq_coalesce_frames() {
+    let mut s = SendQ::new(1400);
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    for _ in 0..100 {
+        s.insert(Reliability::Unreliable, &[0xfe; 30]).unwrap();
+    }
+
+    let ret = s.flush(0, &sockaddr);
+    assert!(ret.len() > 1 && ret.len() < 100);
+
+    let mut frames = 0;
+    for (i, datagram) in ret.iter().enumerate() {
+        assert!(datagram.sequence_number == i as u32);
+        let buf = datagram._serialize().unwrap();
+        assert!(buf.len() <= 1400 - 28);
+        let v = FrameVec::new(buf).unwrap();
+        frames += v.frames.len();
+    }
+    assert!(frames == 100);
+    assert!(s.is_empty());
+}
+
+#[tokio::test]
+async fn test_sendq_window() {
+    let mut s = SendQ::new(1400);
+    s.set_window(4, 1024 * 1024);
+    // the congestion window is larger than 4 datagrams
+    s.set_congestion_controller(Box::new(Cubic::new()));
+    let sockaddr: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+
+    for _ in 0..10 {
+        s.insert(Reliability::Reliable, &[0xfe; 1000]).unwrap();
+    }
+
+    // new sequence numbers go out while older ones are still unacked , up to the window size
+    let ret = s.flush(0, &sockaddr);
+    assert!(ret.len() == 4);
+    assert!(s.get_sent_queue_size() == 4);
+    assert!(s.flush(1, &sockaddr).is_empty());
+
+    s.ack(0, 10);
+    s.ack(1, 10);
+
+    let ret = s.flush(10, &sockaddr);
+    assert!(ret.len() == 2);
+    assert!(ret[0].sequence_number == 4);
+    assert!(ret[1].sequence_number == 5);
+    assert!(s.get_reliable_queue_size() == 4);
+}
+
 #[tokio::test]
 async fn test_client_packet1() {
     let a = [
@@ -1296,5 +3023,6 @@ async fn test_client_packet2() {
         }
     }
 
-    assert!(n == 5);
+    // the client resent its first reliable packet , it is delivered once
+    assert!(n == 4);
 }
@@ -0,0 +1,280 @@
+use std::net::IpAddr;
+
+use crate::error::{RaknetError, Result};
+use crate::utils::cur_timestamp_millis;
+
+// ipv4 mapped ipv6 addresses as ipv4 ones
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(a) => match a.to_ipv4_mapped() {
+            Some(p) => IpAddr::V4(p),
+            None => ip,
+        },
+        p => p,
+    }
+}
+
+// an address or a cidr range , the address is masked to the prefix
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Network {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Network {
+    fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (ip, prefix_len) = match s.split_once('/') {
+            Some((ip, prefix_len)) => (ip, Some(prefix_len)),
+            None => (s, None),
+        };
+        // ipv4 peers of a dual stack listener have ipv4 mapped addresses
+        let addr = match ip.parse::<IpAddr>() {
+            Ok(p) => canonical(p),
+            Err(_) => return Err(RaknetError::IncorrectAddress),
+        };
+        let max_len = Self::max_len(&addr);
+        let prefix_len = match prefix_len {
+            Some(p) => match p.parse::<u8>() {
+                Ok(p) if p <= max_len => p,
+                _ => return Err(RaknetError::IncorrectAddress),
+            },
+            None => max_len,
+        };
+        Ok(Self {
+            addr: Self::mask(addr, prefix_len),
+            prefix_len,
+        })
+    }
+
+    fn max_len(addr: &IpAddr) -> u8 {
+        match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    fn mask(addr: IpAddr, prefix_len: u8) -> IpAddr {
+        match addr {
+            IpAddr::V4(a) => {
+                let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+                IpAddr::V4((u32::from(a) & mask).into())
+            }
+            IpAddr::V6(a) => {
+                let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+                IpAddr::V6((u128::from(a) & mask).into())
+            }
+        }
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = canonical(*ip);
+        ip.is_ipv4() == self.addr.is_ipv4() && Self::mask(ip, self.prefix_len) == self.addr
+    }
+}
+
+impl std::fmt::Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.prefix_len == Self::max_len(&self.addr) {
+            write!(f, "{}", self.addr)
+        } else {
+            write!(f, "{}/{}", self.addr, self.prefix_len)
+        }
+    }
+}
+
+// banned networks of a listener
+pub(crate) struct BanList {
+    // network , unix timestamp in milliseconds the ban expires at , none if it never does
+    entries: Vec<(Network, Option<i64>)>,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    fn purge(&mut self) {
+        let now = cur_timestamp_millis();
+        self.entries.retain(|x| match x.1 {
+            Some(expires) => expires > now,
+            None => true,
+        });
+    }
+
+    fn insert(&mut self, network: Network, expires: Option<i64>) {
+        self.entries.retain(|x| x.0 != network);
+        self.entries.push((network, expires));
+    }
+
+    pub fn ban(&mut self, network: &str, duration: Option<std::time::Duration>) -> Result<()> {
+        let network = Network::parse(network)?;
+        let expires = duration.map(|x| {
+            cur_timestamp_millis().saturating_add(x.as_millis().try_into().unwrap_or(i64::MAX))
+        });
+        self.insert(network, expires);
+        Ok(())
+    }
+
+    pub fn unban(&mut self, network: &str) -> Result<bool> {
+        let network = Network::parse(network)?;
+        self.purge();
+        let len = self.entries.len();
+        self.entries.retain(|x| x.0 != network);
+        Ok(self.entries.len() != len)
+    }
+
+    pub fn is_banned(&mut self, ip: &IpAddr) -> bool {
+        self.purge();
+        self.entries.iter().any(|x| x.0.contains(ip))
+    }
+
+    // one entry per line , the network and the expiry timestamp or `never`
+    pub fn export(&mut self) -> String {
+        self.purge();
+        self.entries
+            .iter()
+            .map(|(network, expires)| match expires {
+                Some(p) => format!("{} {}\n", network, p),
+                None => format!("{} never\n", network),
+            })
+            .collect()
+    }
+
+    // nothing is imported if a line is incorrect , returns the count of imported entries
+    pub fn import(&mut self, bans: &str) -> Result<usize> {
+        let mut entries = vec![];
+        for (i, line) in bans.lines().map(|x| x.trim()).enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (network, expires) = match line.split_once(char::is_whitespace) {
+                Some((network, expires)) => (network, expires.trim()),
+                None => (line, "never"),
+            };
+            let expires = match expires {
+                "never" => None,
+                p => match p.parse::<i64>() {
+                    Ok(p) => Some(p),
+                    Err(_) => return Err(RaknetError::IncorrectBanList(i + 1)),
+                },
+            };
+            let network = match Network::parse(network) {
+                Ok(p) => p,
+                Err(_) => return Err(RaknetError::IncorrectBanList(i + 1)),
+            };
+            entries.push((network, expires));
+        }
+        let count = entries.len();
+        for (network, expires) in entries {
+            self.insert(network, expires);
+        }
+        self.purge();
+        Ok(count)
+    }
+}
+
+#[tokio::test]
+async fn test_network_mask() {
+    let n = Network::parse("10.1.2.3/0").unwrap();
+    assert!(n.to_string() == "0.0.0.0/0");
+    assert!(n.contains(&"255.255.255.255".parse().unwrap()));
+    assert!(!n.contains(&"::1".parse().unwrap()));
+
+    let n = Network::parse("10.1.2.3/32").unwrap();
+    assert!(n.to_string() == "10.1.2.3");
+    assert!(n == Network::parse("10.1.2.3").unwrap());
+    assert!(!n.contains(&"10.1.2.4".parse().unwrap()));
+
+    let n = Network::parse("2001:db8::1/128").unwrap();
+    assert!(n.to_string() == "2001:db8::1");
+    assert!(!n.contains(&"2001:db8::2".parse().unwrap()));
+
+    let n = Network::parse("10.1.2.3/12").unwrap();
+    assert!(n.to_string() == "10.0.0.0/12");
+    assert!(n.contains(&"10.15.255.255".parse().unwrap()));
+    assert!(!n.contains(&"10.16.0.0".parse().unwrap()));
+
+    assert!(Network::parse("10.1.2.3/33").is_err());
+    assert!(Network::parse("2001:db8::1/129").is_err());
+    assert!(Network::parse("10.1.2.3/-1").is_err());
+    assert!(Network::parse("10.1.2.3/").is_err());
+    assert!(Network::parse("10.1.2").is_err());
+}
+
+#[tokio::test]
+async fn test_network_ipv4_mapped() {
+    // ipv4 peers of a dual stack listener match ipv4 bans
+    let n = Network::parse("192.168.0.0/16").unwrap();
+    assert!(n.contains(&"::ffff:192.168.1.1".parse().unwrap()));
+    assert!(!n.contains(&"::ffff:192.169.1.1".parse().unwrap()));
+
+    // and mapped bans are stored as ipv4
+    let n = Network::parse("::ffff:192.168.1.1").unwrap();
+    assert!(n.to_string() == "192.168.1.1");
+    assert!(n.contains(&"192.168.1.1".parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_ban_list_expire() {
+    let mut bans = BanList::new();
+    bans.ban("10.0.0.1", Some(std::time::Duration::from_millis(100)))
+        .unwrap();
+    bans.ban("10.0.0.2", None).unwrap();
+    assert!(bans.is_banned(&"10.0.0.1".parse().unwrap()));
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert!(!bans.is_banned(&"10.0.0.1".parse().unwrap()));
+    assert!(bans.is_banned(&"10.0.0.2".parse().unwrap()));
+    assert!(bans.entries.len() == 1);
+
+    // expired entries are not imported
+    let now = cur_timestamp_millis();
+    assert!(bans.import(&format!("10.0.0.3 {}", now - 1)).unwrap() == 1);
+    assert!(!bans.is_banned(&"10.0.0.3".parse().unwrap()));
+    assert!(bans.entries.len() == 1);
+}
+
+#[tokio::test]
+async fn test_ban_list_import() {
+    let mut bans = BanList::new();
+    bans.ban("10.0.0.1", None).unwrap();
+
+    // nothing is imported if a line is incorrect
+    let lines = "# comment\n\n10.0.0.2 never\n10.0.0.3 soon\n";
+    match bans.import(lines) {
+        Err(RaknetError::IncorrectBanList(4)) => {}
+        _ => panic!("the fourth line is incorrect"),
+    }
+    match bans.import("10.0.0.2\n10.0.0.3/33") {
+        Err(RaknetError::IncorrectBanList(2)) => {}
+        _ => panic!("the second line is incorrect"),
+    }
+    assert!(!bans.is_banned(&"10.0.0.2".parse().unwrap()));
+    assert!(bans.entries.len() == 1);
+
+    // an imported network replaces the existing entry
+    let expires = cur_timestamp_millis() + 60000;
+    assert!(
+        bans.import(&format!("10.0.0.1 {}\n10.0.0.2", expires))
+            .unwrap()
+            == 2
+    );
+    assert!(bans.entries.len() == 2);
+    assert!(bans.entries[0].1 == Some(expires) && bans.entries[1].1.is_none());
+}
+
+#[tokio::test]
+async fn test_ban_list_round_trip() {
+    let mut bans = BanList::new();
+    bans.ban("10.0.0.0/8", None).unwrap();
+    bans.ban("2001:db8::1/32", Some(std::time::Duration::from_secs(60)))
+        .unwrap();
+    bans.ban("::ffff:172.16.0.1", None).unwrap();
+
+    let exported = bans.export();
+    let mut bans2 = BanList::new();
+    assert!(bans2.import(&exported).unwrap() == 3);
+    assert!(bans2.entries == bans.entries);
+    assert!(bans2.export() == exported);
+}
@@ -0,0 +1,296 @@
+use crate::utils::RAKNET_CLIENT_MTU;
+
+/// Congestion control algorithm of a Raknet connection.
+///
+/// SendQ feeds it with ack, nack, rtt and timeout events, keeps at most `window()` unacked bytes in flight
+/// and does not send faster than `pacing_rate()`.
+pub trait CongestionController: Send + Sync {
+    /// Called when the controller is attached to a connection.
+    fn set_mtu(&mut self, mtu: u16);
+    /// A datagram of `bytes` bytes has been acknowledged.
+    fn on_ack(&mut self, bytes: usize, tick: i64);
+    /// The peer reported a datagram as lost.
+    fn on_nack(&mut self, tick: i64);
+    /// A round trip time sample in milliseconds.
+    fn on_rtt(&mut self, rtt: i64);
+    /// A datagram has not been acknowledged within the retransmission timeout.
+    fn on_timeout(&mut self, tick: i64);
+    /// Maximum of unacked bytes in flight.
+    fn window(&self) -> usize;
+    /// Maximum send rate in bytes per second, None if the connection is not paced.
+    fn pacing_rate(&self) -> Option<usize> {
+        None
+    }
+}
+
+// the window never grows beyond this , it keeps an application limited connection from bursting
+const MAX_WINDOW: usize = 64 * 1024 * 1024;
+// same as SendQ::DEFAULT_TIMEOUT_MILLS
+const DEFAULT_RTT: i64 = 50;
+
+/// Sliding window congestion control, the same as the default one of RakNet.
+///
+/// The window grows by one mtu per acked datagram until the slow start threshold, then by one mtu per window.
+/// A nack halves it, a timeout shrinks it to one mtu.
+pub struct SlidingWindow {
+    mtu: usize,
+    cwnd: usize,
+    // 0 means not set yet , the window is in slow start until the first loss
+    ssthresh: usize,
+    srtt: i64,
+    last_backoff_tick: i64,
+}
+
+impl SlidingWindow {
+    pub fn new() -> Self {
+        Self {
+            mtu: RAKNET_CLIENT_MTU as usize,
+            cwnd: RAKNET_CLIENT_MTU as usize,
+            ssthresh: 0,
+            srtt: DEFAULT_RTT,
+            last_backoff_tick: 0,
+        }
+    }
+
+    fn is_slow_start(&self) -> bool {
+        self.ssthresh == 0 || self.cwnd < self.ssthresh
+    }
+
+    // one loss event per round trip , the nacks of one window are the same congestion
+    fn backoff(&mut self, tick: i64) -> bool {
+        if tick - self.last_backoff_tick < self.srtt {
+            return false;
+        }
+        self.last_backoff_tick = tick;
+        self.ssthresh = (self.cwnd / 2).max(self.mtu * 2);
+        true
+    }
+}
+
+impl Default for SlidingWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CongestionController for SlidingWindow {
+    fn set_mtu(&mut self, mtu: u16) {
+        self.mtu = mtu as usize;
+        self.cwnd = self.mtu;
+    }
+
+    fn on_ack(&mut self, bytes: usize, _tick: i64) {
+        if self.is_slow_start() {
+            self.cwnd += bytes;
+        } else {
+            self.cwnd += self.mtu * bytes / self.cwnd;
+        }
+        self.cwnd = self.cwnd.min(MAX_WINDOW);
+    }
+
+    fn on_nack(&mut self, tick: i64) {
+        if self.backoff(tick) {
+            self.cwnd = self.ssthresh;
+        }
+    }
+
+    fn on_rtt(&mut self, rtt: i64) {
+        self.srtt = (self.srtt * 7 + rtt) / 8;
+    }
+
+    fn on_timeout(&mut self, tick: i64) {
+        if self.backoff(tick) {
+            self.cwnd = self.mtu;
+        }
+    }
+
+    fn window(&self) -> usize {
+        self.cwnd
+    }
+}
+
+/// CUBIC congestion control (RFC 8312), it shares the bandwidth fairly with TCP flows.
+///
+/// After a loss the window grows along a cubic function of the time, fast far from the window
+/// of the last loss and slowly around it. Sending is paced over the round trip time.
+pub struct Cubic {
+    mtu: f64,
+    cwnd: f64,
+    ssthresh: f64,
+    // window before the last loss
+    w_max: f64,
+    // seconds to grow back to w_max
+    k: f64,
+    epoch_start: Option<i64>,
+    srtt: i64,
+    last_backoff_tick: i64,
+}
+
+impl Cubic {
+    const C: f64 = 0.4;
+    const BETA: f64 = 0.7;
+    const INITIAL_WINDOW: f64 = 4.0;
+
+    pub fn new() -> Self {
+        let mtu = RAKNET_CLIENT_MTU as f64;
+        Self {
+            mtu,
+            cwnd: mtu * Cubic::INITIAL_WINDOW,
+            ssthresh: f64::MAX,
+            w_max: 0.0,
+            k: 0.0,
+            epoch_start: None,
+            srtt: DEFAULT_RTT,
+            last_backoff_tick: 0,
+        }
+    }
+
+    fn backoff(&mut self, tick: i64) -> bool {
+        if tick - self.last_backoff_tick < self.srtt {
+            return false;
+        }
+        self.last_backoff_tick = tick;
+
+        // fast convergence , release bandwidth to new flows
+        self.w_max = if self.cwnd < self.w_max {
+            self.cwnd * (1.0 + Cubic::BETA) / 2.0
+        } else {
+            self.cwnd
+        };
+        self.cwnd = (self.cwnd * Cubic::BETA).max(self.mtu * 2.0);
+        self.ssthresh = self.cwnd;
+        self.epoch_start = None;
+        true
+    }
+}
+
+impl Default for Cubic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CongestionController for Cubic {
+    fn set_mtu(&mut self, mtu: u16) {
+        self.mtu = mtu as f64;
+        self.cwnd = self.mtu * Cubic::INITIAL_WINDOW;
+    }
+
+    fn on_ack(&mut self, bytes: usize, tick: i64) {
+        let bytes = bytes as f64;
+
+        if self.cwnd < self.ssthresh {
+            self.cwnd = (self.cwnd + bytes).min(MAX_WINDOW as f64);
+            return;
+        }
+
+        let epoch_start = match self.epoch_start {
+            Some(p) => p,
+            None => {
+                if self.cwnd < self.w_max {
+                    self.k = ((self.w_max - self.cwnd) / self.mtu / Cubic::C).cbrt();
+                } else {
+                    self.k = 0.0;
+                    self.w_max = self.cwnd;
+                }
+                self.epoch_start = Some(tick);
+                tick
+            }
+        };
+
+        // W(t) = C * (t - K)^3 + W_max , one round trip ahead
+        let t = (tick - epoch_start + self.srtt) as f64 / 1000.0;
+        let target = self.w_max + Cubic::C * (t - self.k).powi(3) * self.mtu;
+
+        let cubic = if target > self.cwnd {
+            self.cwnd + (target - self.cwnd) * bytes / self.cwnd
+        } else {
+            self.cwnd + self.mtu * bytes / (100.0 * self.cwnd)
+        };
+        // never slower than reno
+        let reno = self.cwnd + self.mtu * bytes / self.cwnd;
+
+        self.cwnd = cubic.max(reno).min(MAX_WINDOW as f64);
+    }
+
+    fn on_nack(&mut self, tick: i64) {
+        self.backoff(tick);
+    }
+
+    fn on_rtt(&mut self, rtt: i64) {
+        self.srtt = (self.srtt * 7 + rtt) / 8;
+    }
+
+    fn on_timeout(&mut self, tick: i64) {
+        if self.backoff(tick) {
+            self.cwnd = self.mtu;
+        }
+    }
+
+    fn window(&self) -> usize {
+        self.cwnd as usize
+    }
+
+    fn pacing_rate(&self) -> Option<usize> {
+        // 1.25 windows per round trip , room for the window to grow
+        let rate = self.cwnd * 1000.0 / self.srtt.max(1) as f64 * 1.25;
+        Some(rate as usize)
+    }
+}
+
+#[tokio::test]
+async fn test_sliding_window() {
+    let mut cc = SlidingWindow::new();
+    cc.set_mtu(1000);
+    assert!(cc.window() == 1000);
+
+    // slow start
+    for i in 0..10 {
+        cc.on_ack(1000, i);
+    }
+    assert!(cc.window() == 11000);
+
+    cc.on_rtt(10);
+    cc.on_nack(100);
+    assert!(cc.window() == 5500);
+    // the other nacks of the same window do not shrink it again
+    cc.on_nack(101);
+    assert!(cc.window() == 5500);
+
+    // congestion avoidance
+    cc.on_ack(1000, 110);
+    assert!(cc.window() > 5500 && cc.window() < 6500);
+
+    cc.on_timeout(200);
+    assert!(cc.window() == 1000);
+}
+
+#[tokio::test]
+async fn test_cubic() {
+    let mut cc = Cubic::new();
+    cc.set_mtu(1000);
+    assert!(cc.window() == 4000);
+    assert!(cc.pacing_rate().is_some());
+
+    for i in 0..96 {
+        cc.on_ack(1000, i);
+    }
+    assert!(cc.window() == 100000);
+
+    cc.on_rtt(10);
+    cc.on_nack(1000);
+    assert!(cc.window() == 70000);
+    cc.on_nack(1001);
+    assert!(cc.window() == 70000);
+
+    // grows back to the window of the loss
+    let mut tick = 1000;
+    while cc.window() < 100000 {
+        tick += 10;
+        cc.on_ack(1000, tick);
+        assert!(tick < 100000);
+    }
+
+    cc.on_timeout(tick + 100);
+    assert!(cc.window() == 1000);
+}
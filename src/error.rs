@@ -1,9 +1,43 @@
+/// Why a connection closed, see `RaknetSocket::disconnect_reason`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// the peer sent a disconnect notification
+    RemoteDisconnect,
+    /// closed or dropped by the application
+    LocalClose,
+    /// nothing received from the peer for 60 seconds
+    Timeout,
+    /// the peer stopped acking , see `RaknetSocket::set_retransmission_limit`
+    ConnectionLost,
+    /// the peer broke the protocol or exceeded a limit
+    ProtocolError,
+    /// the receive buffer overflowed with RecvOverflowPolicy::Disconnect
+    RecvBufferFull,
+    /// the listener which accepted the connection was closed
+    ListenerShutdown,
+    /// kicked by `RaknetListener::kick`
+    Kicked,
+    /// the udp socket failed
+    SocketError,
+}
+
+/// Stage of the handshake which failed, see `RaknetError::ConnectTimeout`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectStage {
+    /// no reply to open connection request 1 , the host is down or is not a raknet server
+    OpenConnectionRequest1,
+    /// no reply to open connection request 2
+    OpenConnectionRequest2,
+    /// the connection request was not accepted
+    ConnectionRequest,
+}
+
 #[derive(Debug)]
 pub enum RaknetError {
     SetRaknetRawSocketError,
     NotListen,
     BindAdressError,
-    ConnectionClosed,
+    ConnectionClosed(DisconnectReason),
     NotSupportVersion,
     IncorrectReply,
     PacketParseError,
@@ -13,6 +47,24 @@ pub enum RaknetError {
     ReadPacketBufferError,
     PacketSizeExceedMTU,
     PacketHeaderError,
+    IncorrectOrderChannel,
+    PacketLost,
+    ReliableWindowOverflow,
+    CompoundTooLarge,
+    TooManyCompounds,
+    FragmentBufferFull,
+    CompoundExpired,
+    WouldBlock,
+    ReceiveWindowFull,
+    ReceiveWindowViolation,
+    ConnectionLost,
+    ConnectTimeout(ConnectStage),
+    AlreadyConnected,
+    NoFreeIncomingConnections,
+    ConnectionBanned,
+    IncorrectAddress,
+    /// the line of an imported ban list which could not be parsed , starting at 1
+    IncorrectBanList(usize),
 }
 
 pub type Result<T> = std::result::Result<T, RaknetError>;
@@ -1,38 +1,91 @@
 use crate::arq::{FrameSetPacket, Reliability};
 use crate::error::*;
+use crate::utils::cur_timestamp_millis;
 use std::collections::HashMap;
 
+/// Limits of the packets being reassembled from fragments on one connection.
+///
+/// A peer exceeding them is disconnected.
+#[derive(Clone, Debug)]
+pub struct FragmentLimits {
+    /// Maximum of fragments of one packet. Default is 1024.
+    pub max_compound_size: u32,
+    /// Maximum of bytes of all the packets being reassembled. Default is 8MB.
+    pub max_total_bytes: usize,
+    /// Maximum of packets being reassembled at the same time. Default is 16.
+    pub max_compounds: usize,
+    /// A packet which has not received a fragment for `timeout` milliseconds expires. Default is 30s.
+    ///
+    /// The fragments of a reliable packet have been acked and are never resent, so the peer is disconnected.
+    /// Unreliable packets are dropped.
+    pub timeout: i64,
+}
+
+impl Default for FragmentLimits {
+    fn default() -> Self {
+        Self {
+            max_compound_size: 1024,
+            max_total_bytes: 8 * 1024 * 1024,
+            max_compounds: 16,
+            timeout: 30000,
+        }
+    }
+}
+
 struct Fragment {
     pub flags: u8,
     pub compound_size: u32,
+    pub sequenced_frame_index: u32,
     pub ordered_frame_index: u32,
+    pub order_channel: u8,
     pub frames: HashMap<u32, FrameSetPacket>,
+    pub bytes: usize,
+    pub last_tick: i64,
 }
 
 impl Fragment {
-    pub fn new(flags: u8, compound_size: u32, ordered_frame_index: u32) -> Self {
+    /// create a compound with the header of one of its fragments
+    pub fn new(frame: &FrameSetPacket) -> Self {
         Self {
-            flags,
-            compound_size,
-            ordered_frame_index,
+            flags: frame.flags,
+            compound_size: frame.compound_size,
+            sequenced_frame_index: frame.sequenced_frame_index,
+            ordered_frame_index: frame.ordered_frame_index,
+            order_channel: frame.order_channel,
             frames: HashMap::new(),
+            bytes: 0,
+            last_tick: cur_timestamp_millis(),
         }
     }
 
+    pub fn is_reliable(&self) -> bool {
+        matches!(
+            Reliability::from((self.flags & 224) >> 5).map(|x| x.without_ack_receipt()),
+            Ok(Reliability::Reliable
+                | Reliability::ReliableOrdered
+                | Reliability::ReliableSequenced)
+        )
+    }
+
     pub fn full(&self) -> bool {
         self.frames.len() == self.compound_size as usize
     }
 
-    pub fn insert(&mut self, frame: FrameSetPacket) {
+    /// return the bytes added to the compound
+    pub fn insert(&mut self, frame: FrameSetPacket) -> usize {
         if self.full() {
-            return;
+            return 0;
         }
 
         if self.frames.contains_key(&frame.fragment_index) {
-            return;
+            return 0;
         }
 
+        let bytes = frame.data.len();
+        self.bytes += bytes;
+        self.last_tick = cur_timestamp_millis();
         self.frames.insert(frame.fragment_index, frame);
+        bytes
     }
 
     pub fn merge(&mut self) -> Result<FrameSetPacket> {
@@ -50,7 +103,9 @@ impl Fragment {
 
         let mut ret = FrameSetPacket::new(Reliability::from((self.flags & 224) >> 5)?, buf);
 
+        ret.sequenced_frame_index = self.sequenced_frame_index;
         ret.ordered_frame_index = self.ordered_frame_index;
+        ret.order_channel = self.order_channel;
         ret.sequence_number = sequence_number;
         Ok(ret)
     }
@@ -58,27 +113,54 @@ impl Fragment {
 
 pub struct FragmentQ {
     fragments: HashMap<u16, Fragment>,
+    limits: FragmentLimits,
+    total_bytes: usize,
 }
 
 impl FragmentQ {
     pub fn new() -> Self {
         Self {
             fragments: HashMap::new(),
+            limits: FragmentLimits::default(),
+            total_bytes: 0,
         }
     }
 
-    pub fn insert(&mut self, frame: FrameSetPacket) {
-        if self.fragments.contains_key(&frame.compound_id) {
+    pub fn set_limits(&mut self, limits: FragmentLimits) {
+        self.limits = limits;
+    }
+
+    pub fn insert(&mut self, frame: FrameSetPacket) -> Result<()> {
+        if frame.compound_size > self.limits.max_compound_size {
+            return Err(RaknetError::CompoundTooLarge);
+        }
+
+        let compound_size = match self.fragments.get(&frame.compound_id) {
+            Some(p) => p.compound_size,
+            None => frame.compound_size,
+        };
+        if frame.fragment_index >= compound_size {
+            return Err(RaknetError::PacketHeaderError);
+        }
+
+        if self.total_bytes + frame.data.len() > self.limits.max_total_bytes {
+            return Err(RaknetError::FragmentBufferFull);
+        }
+
+        if !self.fragments.contains_key(&frame.compound_id) {
+            if self.fragments.len() >= self.limits.max_compounds {
+                return Err(RaknetError::TooManyCompounds);
+            }
             self.fragments
-                .get_mut(&frame.compound_id)
-                .unwrap()
-                .insert(frame);
-        } else {
-            let mut v = Fragment::new(frame.flags, frame.compound_size, frame.ordered_frame_index);
-            let k = frame.compound_id;
-            v.insert(frame);
-            self.fragments.insert(k, v);
+                .insert(frame.compound_id, Fragment::new(&frame));
         }
+
+        self.total_bytes += self
+            .fragments
+            .get_mut(&frame.compound_id)
+            .unwrap()
+            .insert(frame);
+        Ok(())
     }
 
     pub fn flush(&mut self) -> Result<Vec<FrameSetPacket>> {
@@ -90,6 +172,7 @@ impl FragmentQ {
             let a = self.fragments.get_mut(&i).unwrap();
             if a.full() {
                 ret.push(a.merge()?);
+                self.total_bytes -= a.bytes;
                 self.fragments.remove(&i);
             }
         }
@@ -97,7 +180,35 @@ impl FragmentQ {
         Ok(ret)
     }
 
+    /// drop the packets whose fragments stopped arriving , return the number of dropped packets
+    ///
+    /// fails if one of them was reliable , its missing fragments will never come
+    pub fn expire(&mut self, tick: i64) -> Result<usize> {
+        let timeout = self.limits.timeout;
+        let before = self.fragments.len();
+        let mut freed = 0;
+        let mut reliable = false;
+        self.fragments.retain(|_, p| {
+            if tick - p.last_tick > timeout {
+                freed += p.bytes;
+                reliable |= p.is_reliable();
+                false
+            } else {
+                true
+            }
+        });
+        self.total_bytes -= freed;
+        if reliable {
+            return Err(RaknetError::CompoundExpired);
+        }
+        Ok(before - self.fragments.len())
+    }
+
     pub fn size(&self) -> usize {
         self.fragments.len()
     }
+
+    pub fn get_total_bytes(&self) -> usize {
+        self.total_bytes
+    }
 }
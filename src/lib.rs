@@ -39,6 +39,8 @@
 //! - [x] reliable sequenced
 
 mod arq;
+mod ban;
+mod congestion;
 mod datatype;
 pub mod error;
 mod fragment;
@@ -46,12 +48,16 @@ mod log;
 pub mod packet;
 mod server;
 mod socket;
+mod stats;
 mod utils;
 
-pub use crate::arq::Reliability;
+pub use crate::arq::{Priority, Reliability, ReorderThreshold, RtoOptions};
+pub use crate::congestion::*;
+pub use crate::fragment::FragmentLimits;
 pub use crate::log::enable_raknet_log;
 pub use crate::server::*;
 pub use crate::socket::*;
+pub use crate::stats::{ListenerStats, RaknetStats, TrafficStats};
 
 // #[tokio::test]
 // async fn test_ping_pong() {
@@ -156,6 +162,973 @@ async fn test_send_recv_fragment_data() {
     notify.notify_one();
 }
 
+#[tokio::test]
+async fn test_send_recv_order_channel() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+
+    let notify = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify2 = notify.clone();
+
+    tokio::spawn(async move {
+        let client1 = server.accept().await.unwrap();
+        for i in 0..10 {
+            client1
+                .send_on_channel(&[0xfe, i], Reliability::ReliableOrdered, i % 2)
+                .await
+                .unwrap();
+        }
+        assert!(client1
+            .send_on_channel(&[0xfe], Reliability::ReliableOrdered, 32)
+            .await
+            .is_err());
+        notify2.notified().await;
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+
+    let mut last = [None, None];
+    for _ in 0..10 {
+        let (buf, info) = client2.recv_with_info().await.unwrap();
+        assert!(info.reliability == Reliability::ReliableOrdered);
+        assert!(info.order_channel == buf[1] % 2);
+        let channel = info.order_channel as usize;
+        if let Some(p) = last[channel] {
+            assert!(buf[1] == p + 2);
+        }
+        last[channel] = Some(buf[1]);
+    }
+
+    notify.notify_one();
+}
+
+#[tokio::test]
+async fn test_send_with_receipt() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+
+    let notify = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify2 = notify.clone();
+
+    tokio::spawn(async move {
+        let client1 = server.accept().await.unwrap();
+        let receipt = client1
+            .send_with_receipt(&[0xfe; 3000], Reliability::ReliableOrderedWithAckReceipt)
+            .await
+            .unwrap();
+        receipt.wait().await.unwrap();
+        assert!(client1
+            .send_with_receipt(&[0xfe], Reliability::Reliable)
+            .await
+            .is_err());
+        notify2.notify_one();
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    let (buf, info) = client2.recv_with_info().await.unwrap();
+    assert!(buf == vec![0xfe; 3000]);
+    assert!(info.reliability == Reliability::ReliableOrdered);
+
+    notify.notified().await;
+}
+
+#[tokio::test]
+async fn test_fragment_limits() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.set_fragment_limits(FragmentLimits {
+        max_compound_size: 4,
+        ..FragmentLimits::default()
+    });
+    server.listen().await;
+
+    let notify = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify2 = notify.clone();
+
+    tokio::spawn(async move {
+        let client1 = server.accept().await.unwrap();
+        assert!(client1.recv().await.unwrap() == vec![0xfe; 3000]);
+        // the peer is disconnected instead of buffering the packet
+        assert!(client1.recv().await.is_err());
+        notify2.notify_one();
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    client2
+        .send(&[0xfe; 3000], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    client2
+        .send(&[0xfe; 10000], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+
+    notify.notified().await;
+}
+
+#[tokio::test]
+async fn test_keepalive_rtt() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.set_keepalive_interval(100);
+    server.listen().await;
+
+    let notify = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify2 = notify.clone();
+    let notify3 = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify4 = notify3.clone();
+
+    tokio::spawn(async move {
+        let client1 = server.accept().await.unwrap();
+        // measured by the keepalive pings
+        while client1.last_rtt().is_none() {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!((0..100).contains(&client1.last_rtt().unwrap()));
+        // keep the connection until the client is done
+        notify4.notified().await;
+        notify2.notify_one();
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    let rtt = client2.ping_rtt().await.unwrap();
+    assert!((0..100).contains(&rtt));
+    assert!(client2.last_rtt().is_some());
+    // both ends share the clock
+    assert!(client2.clock_offset().unwrap().abs() < 100);
+    notify3.notify_one();
+
+    notify.notified().await;
+}
+
+#[tokio::test]
+async fn test_keepalive_lost() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.set_keepalive_interval(100);
+    server.set_retransmission_limit(3, 1000);
+    server.listen().await;
+
+    // an idle peer which acks everything until it goes silent
+    let s = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    raw_connect(&s, &local_addr).await;
+    let client1 = server.accept().await.unwrap();
+    let peer = tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        loop {
+            let (size, _) = s.recv_from(&mut buf).await.unwrap();
+            if buf[0] < packet::PacketID::FrameSetPacketBegin.to_u8()
+                || buf[0] > packet::PacketID::FrameSetPacketEnd.to_u8()
+            {
+                continue;
+            }
+            let frames = arq::FrameVec::new(buf[..size].to_vec()).unwrap();
+            let ack = packet::write_packet_ack(&packet::Ack {
+                record_count: 1,
+                sequences: vec![(frames.sequence_number, frames.sequence_number)],
+            })
+            .unwrap();
+            s.send_to(&ack, local_addr).await.unwrap();
+        }
+    });
+
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert!(client1.disconnect_reason().is_none());
+
+    peer.abort();
+    let begin = std::time::Instant::now();
+    match tokio::time::timeout(std::time::Duration::from_secs(5), client1.recv()).await {
+        Ok(Err(error::RaknetError::ConnectionLost)) => {}
+        p => panic!("{:?}", p),
+    }
+    assert!(begin.elapsed() < std::time::Duration::from_millis(2000));
+}
+
+// open a connection from a bare udp socket , returns the send queue to forge the next datagrams
+#[cfg(test)]
+async fn raw_connect(s: &tokio::net::UdpSocket, addr: &std::net::SocketAddr) -> arq::SendQ {
+    raw_open_connection(s, addr).await;
+
+    let mut sendq = arq::SendQ::new(1400);
+    let request = packet::write_packet_connection_request(&packet::ConnectionRequest {
+        guid: 1,
+        time: utils::cur_timestamp_millis(),
+        use_encryption: 0,
+    })
+    .unwrap();
+    sendq
+        .insert(Reliability::ReliableOrdered, &request)
+        .unwrap();
+    let incomming = packet::write_packet_new_incomming_connection(&packet::NewIncomingConnection {
+        server_address: *addr,
+        request_timestamp: 0,
+        accepted_timestamp: 0,
+    })
+    .unwrap();
+    sendq
+        .insert(Reliability::ReliableOrdered, &incomming)
+        .unwrap();
+    for f in sendq.flush(utils::cur_timestamp_millis(), addr) {
+        s.send_to(&f._serialize().unwrap(), addr).await.unwrap();
+    }
+    sendq
+}
+
+// send request1 and request2 , the handshake then stalls
+#[cfg(test)]
+async fn raw_open_connection(s: &tokio::net::UdpSocket, addr: &std::net::SocketAddr) {
+    let mut buf = [0u8; 2048];
+    let request1 =
+        packet::write_packet_connection_open_request_1(&packet::OpenConnectionRequest1 {
+            magic: true,
+            protocol_version: utils::RAKNET_PROTOCOL_VERSION,
+            mtu_size: 1400,
+        })
+        .unwrap();
+    s.send_to(&request1, addr).await.unwrap();
+    s.recv_from(&mut buf).await.unwrap();
+    assert!(buf[0] == packet::PacketID::OpenConnectionReply1.to_u8());
+
+    let request2 =
+        packet::write_packet_connection_open_request_2(&packet::OpenConnectionRequest2 {
+            magic: true,
+            address: *addr,
+            mtu: 1400,
+            guid: 1,
+        })
+        .unwrap();
+    s.send_to(&request2, addr).await.unwrap();
+    s.recv_from(&mut buf).await.unwrap();
+    assert!(buf[0] == packet::PacketID::OpenConnectionReply2.to_u8());
+}
+
+#[tokio::test]
+async fn test_stats() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+
+    let notify = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify2 = notify.clone();
+    let notify3 = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify4 = notify3.clone();
+
+    tokio::spawn(async move {
+        let client1 = server.accept().await.unwrap();
+        for _ in 0..10 {
+            assert!(client1.recv().await.unwrap() == vec![0xfe; 100]);
+        }
+        let stats = client1.stats().await;
+        assert!(
+            stats.traffic.messages_received[Reliability::ReliableOrdered.to_u8() as usize] == 10
+        );
+        assert!(stats.traffic.datagrams_received > 0 && stats.traffic.bytes_received > 1000);
+        assert!(stats.recv_buffer_bytes == 0);
+
+        let stats = server.stats().await;
+        assert!(stats.sessions == 1);
+        assert!(
+            stats.traffic.messages_received[Reliability::ReliableOrdered.to_u8() as usize] == 10
+        );
+        // keep the connection until the client is done
+        notify4.notified().await;
+        notify2.notify_one();
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    for _ in 0..10 {
+        client2
+            .send(&[0xfe; 100], Reliability::ReliableOrdered)
+            .await
+            .unwrap();
+    }
+    client2.flush().await.unwrap();
+    let stats = client2.stats().await;
+    assert!(stats.traffic.messages_sent[Reliability::ReliableOrdered.to_u8() as usize] == 10);
+    assert!(stats.traffic.bytes_sent > 1000);
+    assert!(stats.send_queue_bytes == 0 && stats.unacked_datagrams == 0);
+    assert!(stats.traffic.loss_rate() == 0.0);
+    assert!(stats.traffic.data_datagrams_sent > 0);
+    notify3.notify_one();
+
+    notify.notified().await;
+
+    // ordered packets behind a lost datagram are held back
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+
+    let s = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut sendq = raw_connect(&s, &local_addr).await;
+    let client1 = server.accept().await.unwrap();
+
+    let mut datagrams = vec![];
+    for _ in 0..2 {
+        sendq
+            .insert(Reliability::ReliableOrdered, &[0xfe; 100])
+            .unwrap();
+        datagrams.append(&mut sendq.flush(utils::cur_timestamp_millis(), &local_addr));
+    }
+    assert!(datagrams.len() == 2);
+    s.send_to(&datagrams[1]._serialize().unwrap(), local_addr)
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert!(client1.stats().await.recv_queue_size == 1);
+
+    s.send_to(&datagrams[0]._serialize().unwrap(), local_addr)
+        .await
+        .unwrap();
+    for _ in 0..2 {
+        assert!(client1.recv().await.unwrap() == vec![0xfe; 100]);
+    }
+    assert!(client1.stats().await.recv_queue_size == 0);
+}
+
+#[tokio::test]
+async fn test_loss_rate() {
+    // the peer receiving data mostly sends acks , they do not dilute the loss rate
+    let stats = TrafficStats {
+        datagrams_sent: 100,
+        data_datagrams_sent: 10,
+        retransmissions: 2,
+        ..TrafficStats::default()
+    };
+    assert!(stats.loss_rate() == 0.2);
+    assert!(TrafficStats::default().loss_rate() == 0.0);
+}
+
+#[tokio::test]
+async fn test_mtu_negotiation() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.set_max_mtu(1200);
+    server.listen().await;
+
+    let notify = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify2 = notify.clone();
+    let notify3 = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify4 = notify3.clone();
+
+    tokio::spawn(async move {
+        let client1 = server.accept().await.unwrap();
+        assert!(client1.mtu().await == 1200);
+        assert!(client1.recv().await.unwrap() == vec![0xfe; 5000]);
+        // keep the connection until the client is done
+        notify4.notified().await;
+        notify2.notify_one();
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    assert!(client2.mtu().await == 1200);
+    client2
+        .send(&[0xfe; 5000], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    client2.flush().await.unwrap();
+    notify3.notify_one();
+    notify.notified().await;
+
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+    tokio::spawn(async move {
+        let _client1 = server.accept().await.unwrap();
+        std::future::pending::<()>().await;
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    assert!(client2.mtu().await == utils::RAKNET_MAX_MTU);
+}
+
+#[tokio::test]
+async fn test_connect_timeout() {
+    let options = ConnectOptions {
+        timeout: std::time::Duration::from_secs(5),
+        attempt_timeout: std::time::Duration::from_millis(100),
+        max_attempts: 3,
+        ..ConnectOptions::default()
+    };
+
+    // nothing answers
+    let s = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = s.local_addr().unwrap();
+    let begin = std::time::Instant::now();
+    match RaknetSocket::connect_with_options(&addr, options.clone()).await {
+        Err(error::RaknetError::ConnectTimeout(error::ConnectStage::OpenConnectionRequest1)) => {}
+        _ => panic!("connect should fail"),
+    }
+    assert!(begin.elapsed() < std::time::Duration::from_secs(2));
+
+    // only the first stage is answered
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 2048];
+        loop {
+            let (_, src) = s.recv_from(&mut buf).await.unwrap();
+            if buf[0] != packet::PacketID::OpenConnectionRequest1.to_u8() {
+                continue;
+            }
+            let reply =
+                packet::write_packet_connection_open_reply_1(&packet::OpenConnectionReply1 {
+                    magic: true,
+                    guid: 1,
+                    use_encryption: 0,
+                    mtu_size: 1400,
+                })
+                .unwrap();
+            s.send_to(&reply, src).await.unwrap();
+        }
+    });
+    match RaknetSocket::connect_with_options(&addr, options).await {
+        Err(error::RaknetError::ConnectTimeout(error::ConnectStage::OpenConnectionRequest2)) => {}
+        _ => panic!("connect should fail"),
+    }
+
+    // the total deadline is shorter than the attempts
+    let s = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let begin = std::time::Instant::now();
+    match RaknetSocket::connect_timeout(
+        &s.local_addr().unwrap(),
+        std::time::Duration::from_millis(300),
+    )
+    .await
+    {
+        Err(error::RaknetError::ConnectTimeout(error::ConnectStage::OpenConnectionRequest1)) => {}
+        _ => panic!("connect should fail"),
+    }
+    assert!(begin.elapsed() < std::time::Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_connect_refused() {
+    // a fake server answering request1 with reply1 , and request2 with the refusal
+    async fn refusing_server(refusal: Vec<u8>, at_request1: bool) -> std::net::SocketAddr {
+        let s = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = s.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 2048];
+            loop {
+                let (_, src) = s.recv_from(&mut buf).await.unwrap();
+                let reply = if buf[0] == packet::PacketID::OpenConnectionRequest1.to_u8()
+                    && !at_request1
+                {
+                    packet::write_packet_connection_open_reply_1(&packet::OpenConnectionReply1 {
+                        magic: true,
+                        guid: 1,
+                        use_encryption: 0,
+                        mtu_size: 1400,
+                    })
+                    .unwrap()
+                } else {
+                    refusal.clone()
+                };
+                s.send_to(&reply, src).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    let banned = packet::write_packet_connection_banned(&packet::ConnectionBanned {
+        magic: true,
+        guid: 1,
+    })
+    .unwrap();
+    let addr = refusing_server(banned, true).await;
+    match RaknetSocket::connect(&addr).await {
+        Err(error::RaknetError::ConnectionBanned) => {}
+        _ => panic!("connect should fail"),
+    }
+
+    let full =
+        packet::write_packet_no_free_incoming_connections(&packet::NoFreeIncomingConnections {
+            magic: true,
+            guid: 1,
+        })
+        .unwrap();
+    let addr = refusing_server(full, false).await;
+    match RaknetSocket::connect(&addr).await {
+        Err(error::RaknetError::NoFreeIncomingConnections) => {}
+        _ => panic!("connect should fail"),
+    }
+
+    let already = packet::write_packet_already_connected(&packet::AlreadyConnected {
+        magic: true,
+        guid: 1,
+    })
+    .unwrap();
+    let addr = refusing_server(already, false).await;
+    match RaknetSocket::connect(&addr).await {
+        Err(error::RaknetError::AlreadyConnected) => {}
+        _ => panic!("connect should fail"),
+    }
+}
+
+#[tokio::test]
+async fn test_max_sessions() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.set_max_sessions(1);
+    server.listen().await;
+
+    let (_, motd) = RaknetSocket::ping(&local_addr).await.unwrap();
+    let fields: Vec<&str> = motd.split(';').collect();
+    assert!(fields[4] == "0" && fields[5] == "1");
+
+    // a stalled handshake does not use up the slot
+    let stalled = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    raw_open_connection(&stalled, &local_addr).await;
+    let (_, motd) = RaknetSocket::ping(&local_addr).await.unwrap();
+    let fields: Vec<&str> = motd.split(';').collect();
+    assert!(fields[4] == "0");
+    assert!(server.stats().await.sessions == 0);
+
+    tokio::spawn(async move {
+        let _client1 = server.accept().await.unwrap();
+        std::future::pending::<()>().await;
+    });
+    let _client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    // the slot is taken once the listener received the last handshake packet
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    match RaknetSocket::connect(&local_addr).await {
+        Err(error::RaknetError::NoFreeIncomingConnections) => {}
+        _ => panic!("the listener is full"),
+    }
+
+    let (_, motd) = RaknetSocket::ping(&local_addr).await.unwrap();
+    let fields: Vec<&str> = motd.split(';').collect();
+    assert!(fields[4] == "1" && fields[5] == "1");
+
+    // open connections only ping
+    let s = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut ping = packet::write_packet_ping(&packet::PacketUnconnectedPing {
+        time: utils::cur_timestamp_millis(),
+        magic: true,
+        guid: 1,
+    })
+    .unwrap();
+    ping[0] = packet::PacketID::UnconnectedPing2.to_u8();
+    s.send_to(&ping, local_addr).await.unwrap();
+    let mut buf = [0u8; 1024];
+    assert!(
+        tokio::time::timeout(std::time::Duration::from_millis(500), s.recv_from(&mut buf))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_max_sessions_concurrent() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.set_max_sessions(2);
+    server.listen().await;
+
+    // every request2 arrives before any handshake is finished
+    let mut clients = vec![];
+    for _ in 0..8 {
+        clients.push(tokio::spawn(async move {
+            RaknetSocket::connect(&local_addr).await
+        }));
+    }
+
+    let mut accepted = vec![];
+    while let Ok(p) =
+        tokio::time::timeout(std::time::Duration::from_millis(1000), server.accept()).await
+    {
+        accepted.push(p.unwrap());
+    }
+    assert!(accepted.len() == 2);
+
+    let (_, motd) = RaknetSocket::ping(&local_addr).await.unwrap();
+    let fields: Vec<&str> = motd.split(';').collect();
+    assert!(fields[4] == "2");
+
+    // the peers over the limit are disconnected
+    let mut refused = 0;
+    for client in clients {
+        match client.await.unwrap() {
+            Ok(client) => {
+                if tokio::time::timeout(std::time::Duration::from_millis(500), client.recv())
+                    .await
+                    .is_ok()
+                {
+                    refused += 1;
+                }
+            }
+            Err(_) => refused += 1,
+        }
+    }
+    assert!(refused == 6);
+}
+
+#[tokio::test]
+async fn test_ban() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+
+    let client = tokio::spawn(async move { RaknetSocket::connect(&local_addr).await.unwrap() });
+    let client1 = server.accept().await.unwrap();
+    let client2 = client.await.unwrap();
+
+    // the existing session is kicked
+    server
+        .ban("127.0.0.0/8", Some(std::time::Duration::from_millis(1000)))
+        .await
+        .unwrap();
+    match client1.recv().await {
+        Err(error::RaknetError::ConnectionClosed(error::DisconnectReason::Kicked)) => {}
+        p => panic!("{:?}", p),
+    }
+    assert!(client2.recv().await.is_err());
+
+    match RaknetSocket::connect(&local_addr).await {
+        Err(error::RaknetError::ConnectionBanned) => {}
+        _ => panic!("the address is banned"),
+    }
+    assert!(server.is_banned(&"127.1.2.3".parse().unwrap()));
+    assert!(server.is_banned(&"::ffff:127.0.0.1".parse().unwrap()));
+    assert!(!server.is_banned(&"128.0.0.1".parse().unwrap()));
+
+    // export and import
+    server.ban("2001:db8::1/32", None).await.unwrap();
+    let bans = server.export_bans();
+    assert!(bans.lines().count() == 2 && bans.contains("2001:db8::/32 never"));
+    let server2 = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    assert!(server2.import_bans("10.0.0.1/33 never").await.is_err());
+    assert!(server2.import_bans(&bans).await.unwrap() == 2);
+    assert!(server2.is_banned(&"2001:db8:1::5".parse().unwrap()));
+    assert!(server2.unban("2001:db8::/32").unwrap());
+    assert!(!server2.is_banned(&"2001:db8:1::5".parse().unwrap()));
+
+    // the ban expires
+    tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
+    assert!(!server.is_banned(&"127.0.0.1".parse().unwrap()));
+    let client = tokio::spawn(async move { RaknetSocket::connect(&local_addr).await.unwrap() });
+    let _client1 = server.accept().await.unwrap();
+    let _client2 = client.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_disconnect_reason() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+
+    let notify = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify2 = notify.clone();
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+
+    tokio::spawn(async move {
+        // the peer closes
+        let client1 = server.accept().await.unwrap();
+        match client1.recv().await {
+            Err(error::RaknetError::ConnectionClosed(
+                error::DisconnectReason::RemoteDisconnect,
+            )) => {}
+            p => panic!("{:?}", p),
+        }
+        assert!(client1.disconnect_reason() == Some(error::DisconnectReason::RemoteDisconnect));
+
+        // the listener kicks
+        let client1 = server.accept().await.unwrap();
+        assert!(server.kick(&client1.peer_addr().unwrap()).await);
+        match client1.recv().await {
+            Err(error::RaknetError::ConnectionClosed(error::DisconnectReason::Kicked)) => {}
+            p => panic!("{:?}", p),
+        }
+
+        // the listener shuts down
+        let client1 = server.accept().await.unwrap();
+        sender.send(()).await.unwrap();
+        server.close().await.unwrap();
+        assert!(client1.recv().await.is_err());
+        assert!(client1.disconnect_reason() == Some(error::DisconnectReason::ListenerShutdown));
+        notify2.notify_one();
+    });
+
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    client2.close().await.unwrap();
+    assert!(client2.disconnect_reason() == Some(error::DisconnectReason::LocalClose));
+
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    assert!(client2.recv().await.is_err());
+    assert!(client2.disconnect_reason() == Some(error::DisconnectReason::RemoteDisconnect));
+
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    assert!(client2.disconnect_reason().is_none());
+    receiver.recv().await.unwrap();
+
+    notify.notified().await;
+    drop(client2);
+}
+
+#[tokio::test]
+async fn test_close_graceful() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+
+    let notify = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify2 = notify.clone();
+
+    tokio::spawn(async move {
+        let mut client1 = server.accept().await.unwrap();
+        client1.set_loss_rate(8);
+        for i in 0..50 {
+            client1
+                .send(&[0xfe, i], Reliability::ReliableOrdered)
+                .await
+                .unwrap();
+        }
+        assert!(client1
+            .close_graceful(std::time::Duration::from_secs(10))
+            .await
+            .unwrap());
+        assert!(client1.disconnect_reason() == Some(error::DisconnectReason::LocalClose));
+        match client1.send(&[0xfe], Reliability::ReliableOrdered).await {
+            Err(error::RaknetError::ConnectionClosed(error::DisconnectReason::LocalClose)) => {}
+            p => panic!("{:?}", p),
+        }
+        notify2.notify_one();
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    // every packet sent before the close arrives
+    for i in 0..50 {
+        assert!(client2.recv().await.unwrap() == vec![0xfe, i]);
+    }
+    match client2.recv().await {
+        Err(error::RaknetError::ConnectionClosed(error::DisconnectReason::RemoteDisconnect)) => {}
+        p => panic!("{:?}", p),
+    }
+
+    notify.notified().await;
+}
+
+#[tokio::test]
+async fn test_close_graceful_echo() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+
+    // the peer acks everything but the disconnect notification , it only answers with its own
+    let s = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    raw_connect(&s, &local_addr).await;
+    let client1 = server.accept().await.unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        loop {
+            let (size, _) = s.recv_from(&mut buf).await.unwrap();
+            if buf[0] < packet::PacketID::FrameSetPacketBegin.to_u8()
+                || buf[0] > packet::PacketID::FrameSetPacketEnd.to_u8()
+            {
+                continue;
+            }
+            let frames = arq::FrameVec::new(buf[..size].to_vec()).unwrap();
+            if frames
+                .frames
+                .iter()
+                .any(|x| x.data[0] == packet::PacketID::Disconnect.to_u8())
+            {
+                s.send_to(&[packet::PacketID::Disconnect.to_u8()], local_addr)
+                    .await
+                    .unwrap();
+                continue;
+            }
+            let ack = packet::write_packet_ack(&packet::Ack {
+                record_count: 1,
+                sequences: vec![(frames.sequence_number, frames.sequence_number)],
+            })
+            .unwrap();
+            s.send_to(&ack, local_addr).await.unwrap();
+        }
+    });
+
+    assert!(client1
+        .close_graceful(std::time::Duration::from_secs(5))
+        .await
+        .unwrap());
+    assert!(client1.disconnect_reason() == Some(error::DisconnectReason::LocalClose));
+}
+
+#[tokio::test]
+async fn test_send_buffer() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+
+    let notify = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify2 = notify.clone();
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+
+    tokio::spawn(async move {
+        let client1 = server.accept().await.unwrap();
+        client1.set_send_buffer(10000).await;
+
+        let mut sent = 0;
+        loop {
+            match client1
+                .try_send(&[0xfe; 1000], Reliability::ReliableOrdered)
+                .await
+            {
+                Ok(_) => sent += 1,
+                Err(error::RaknetError::WouldBlock) => break,
+                Err(e) => panic!("{:?}", e),
+            }
+            assert!(sent < 1000);
+        }
+        assert!(client1.queued_bytes().await + client1.inflight_bytes().await >= 10000);
+
+        // send waits for the acks instead of failing
+        for _ in 0..100 {
+            client1
+                .send(&[0xfe; 1000], Reliability::ReliableOrdered)
+                .await
+                .unwrap();
+            assert!(client1.queued_bytes().await <= 11000);
+        }
+        sender.send(sent + 100).await.unwrap();
+        client1.flush().await.unwrap();
+        assert!(client1.queued_bytes().await == 0);
+        assert!(client1.inflight_bytes().await == 0);
+        notify2.notify_one();
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    // keep reading , the sender needs the acks
+    for _ in 0..100 {
+        let buf = client2.recv().await.unwrap();
+        assert!(buf == vec![0xfe; 1000]);
+    }
+    let n = receiver.recv().await.unwrap();
+    for _ in 100..n {
+        let buf = client2.recv().await.unwrap();
+        assert!(buf == vec![0xfe; 1000]);
+    }
+
+    notify.notified().await;
+}
+
+#[tokio::test]
+async fn test_recv_buffer() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.listen().await;
+
+    let notify = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify2 = notify.clone();
+    let notify3 = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify4 = notify3.clone();
+
+    tokio::spawn(async move {
+        let client1 = server.accept().await.unwrap();
+        for i in 0..500u32 {
+            let mut buf = vec![0xfe];
+            buf.extend_from_slice(&i.to_be_bytes());
+            client1
+                .send(&buf, Reliability::ReliableOrdered)
+                .await
+                .unwrap();
+        }
+        // acked although the peer has not read anything yet
+        client1.flush().await.unwrap();
+        notify2.notify_one();
+
+        notify4.notified().await;
+        for _ in 0..10 {
+            client1
+                .send(&[0xfe; 100], Reliability::ReliableOrdered)
+                .await
+                .unwrap();
+        }
+        client1.flush().await.unwrap();
+        notify2.notify_one();
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    notify.notified().await;
+    assert!(client2.recv_buffer_bytes().await == 500 * 5);
+    for i in 0..500u32 {
+        let buf = client2.recv().await.unwrap();
+        assert!(buf[1..] == i.to_be_bytes());
+    }
+
+    // keep the last 3 packets only
+    client2
+        .set_recv_buffer(300, RecvOverflowPolicy::DropOldest)
+        .await;
+    notify3.notify_one();
+    notify.notified().await;
+    assert!(client2.recv_buffer_bytes().await == 300);
+}
+
+#[tokio::test]
+async fn test_send_recv_cubic() {
+    let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let local_addr = server.local_addr().unwrap();
+    server.set_congestion_controller(|| Box::new(Cubic::new()));
+    server.listen().await;
+
+    tokio::spawn(async move {
+        let client1 = server.accept().await.unwrap();
+        for _ in 0..100 {
+            let buf = client1.recv().await.unwrap();
+            client1
+                .send(&buf, Reliability::ReliableOrdered)
+                .await
+                .unwrap();
+        }
+        client1.flush().await.unwrap();
+    });
+    let client2 = RaknetSocket::connect(&local_addr).await.unwrap();
+    client2
+        .set_congestion_controller(Box::new(Cubic::new()))
+        .await;
+
+    for i in 0..100 {
+        client2
+            .send(&[0xfe, i, 1, 2, 3], Reliability::ReliableOrdered)
+            .await
+            .unwrap();
+    }
+    for i in 0..100 {
+        let buf = client2.recv().await.unwrap();
+        assert!(buf == vec![0xfe, i, 1, 2, 3]);
+    }
+}
+
 #[tokio::test]
 async fn test_send_recv_more_reliability_type_packet() {
     let mut server = RaknetListener::bind(&"127.0.0.1:0".parse().unwrap())
@@ -544,7 +1517,7 @@ async fn test_send_recv_with_flush() {
                 Ok(_) => panic!("incorrect return"),
                 Err(e) => e,
             } {
-                error::RaknetError::ConnectionClosed => {}
+                error::RaknetError::ConnectionClosed(error::DisconnectReason::LocalClose) => {}
                 _ => panic!("incorrect return"),
             };
         }
@@ -20,7 +20,9 @@ pub enum PacketID {
     ConnectionRequestAccepted = 0x10,
     AlreadyConnected = 0x12,
     NewIncomingConnection = 0x13,
+    NoFreeIncomingConnections = 0x14,
     Disconnect = 0x15,
+    ConnectionBanned = 0x17,
     IncompatibleProtocolVersion = 0x19,
     FrameSetPacketBegin = 0x80,
     FrameSetPacketEnd = 0x8d,
@@ -45,7 +47,9 @@ impl PacketID {
             PacketID::ConnectionRequestAccepted => 0x10,
             PacketID::AlreadyConnected => 0x12,
             PacketID::NewIncomingConnection => 0x13,
+            PacketID::NoFreeIncomingConnections => 0x14,
             PacketID::Disconnect => 0x15,
+            PacketID::ConnectionBanned => 0x17,
             PacketID::IncompatibleProtocolVersion => 0x19,
             PacketID::FrameSetPacketBegin => 0x80,
             PacketID::FrameSetPacketEnd => 0x8d,
@@ -74,7 +78,9 @@ impl PacketID {
             0x10 => Ok(PacketID::ConnectionRequestAccepted),
             0x12 => Ok(PacketID::AlreadyConnected),
             0x13 => Ok(PacketID::NewIncomingConnection),
+            0x14 => Ok(PacketID::NoFreeIncomingConnections),
             0x15 => Ok(PacketID::Disconnect),
+            0x17 => Ok(PacketID::ConnectionBanned),
             0x19 => Ok(PacketID::IncompatibleProtocolVersion),
             0x80 => Ok(PacketID::FrameSetPacketBegin),
             0x8d => Ok(PacketID::FrameSetPacketEnd),
@@ -190,6 +196,18 @@ pub struct AlreadyConnected {
     pub guid: u64,
 }
 
+#[derive(Clone)]
+pub struct NoFreeIncomingConnections {
+    pub magic: bool,
+    pub guid: u64,
+}
+
+#[derive(Clone)]
+pub struct ConnectionBanned {
+    pub magic: bool,
+    pub guid: u64,
+}
+
 #[derive(Clone)]
 pub struct Nack {
     pub record_count: u16,
@@ -333,7 +351,7 @@ pub fn write_packet_connection_open_reply_2(packet: &OpenConnectionReply2) -> Re
     Ok(cursor.get_raw_payload())
 }
 
-pub fn _read_packet_already_connected(buf: &[u8]) -> Result<AlreadyConnected> {
+pub fn read_packet_already_connected(buf: &[u8]) -> Result<AlreadyConnected> {
     let mut cursor = RaknetReader::new(buf.to_vec());
     unwrap_or_return!(cursor.read_u8());
     Ok(AlreadyConnected {
@@ -350,6 +368,42 @@ pub fn write_packet_already_connected(packet: &AlreadyConnected) -> Result<Vec<u
     Ok(cursor.get_raw_payload())
 }
 
+pub fn read_packet_no_free_incoming_connections(buf: &[u8]) -> Result<NoFreeIncomingConnections> {
+    let mut cursor = RaknetReader::new(buf.to_vec());
+    unwrap_or_return!(cursor.read_u8());
+    Ok(NoFreeIncomingConnections {
+        magic: unwrap_or_return!(cursor.read_magic()),
+        guid: unwrap_or_return!(cursor.read_u64(Endian::Big)),
+    })
+}
+
+pub fn write_packet_no_free_incoming_connections(
+    packet: &NoFreeIncomingConnections,
+) -> Result<Vec<u8>> {
+    let mut cursor = RaknetWriter::new();
+    unwrap_or_return!(cursor.write_u8(PacketID::NoFreeIncomingConnections.to_u8()));
+    unwrap_or_return!(cursor.write_magic());
+    unwrap_or_return!(cursor.write_u64(packet.guid, Endian::Big));
+    Ok(cursor.get_raw_payload())
+}
+
+pub fn read_packet_connection_banned(buf: &[u8]) -> Result<ConnectionBanned> {
+    let mut cursor = RaknetReader::new(buf.to_vec());
+    unwrap_or_return!(cursor.read_u8());
+    Ok(ConnectionBanned {
+        magic: unwrap_or_return!(cursor.read_magic()),
+        guid: unwrap_or_return!(cursor.read_u64(Endian::Big)),
+    })
+}
+
+pub fn write_packet_connection_banned(packet: &ConnectionBanned) -> Result<Vec<u8>> {
+    let mut cursor = RaknetWriter::new();
+    unwrap_or_return!(cursor.write_u8(PacketID::ConnectionBanned.to_u8()));
+    unwrap_or_return!(cursor.write_magic());
+    unwrap_or_return!(cursor.write_u64(packet.guid, Endian::Big));
+    Ok(cursor.get_raw_payload())
+}
+
 pub fn read_packet_incompatible_protocol_version(
     buf: &[u8],
 ) -> Result<IncompatibleProtocolVersion> {
@@ -457,7 +511,7 @@ pub fn write_packet_ack(packet: &Ack) -> Result<Vec<u8>> {
             } else {
                 0u8
             };
-        unwrap_or_return!(cursor.write_u8(single_sequence_number as u8));
+        unwrap_or_return!(cursor.write_u8(single_sequence_number));
         unwrap_or_return!(cursor.write_u24(packet.sequences[i as usize].0, Endian::Little));
         if single_sequence_number == 0x00 {
             unwrap_or_return!(cursor.write_u24(packet.sequences[i as usize].1, Endian::Little));
@@ -562,7 +616,7 @@ pub fn write_packet_connected_ping(packet: &ConnectedPing) -> Result<Vec<u8>> {
     Ok(cursor.get_raw_payload())
 }
 
-pub fn _read_packet_connected_pong(buf: &[u8]) -> Result<ConnectedPong> {
+pub fn read_packet_connected_pong(buf: &[u8]) -> Result<ConnectedPong> {
     let mut cursor = RaknetReader::new(buf.to_vec());
     unwrap_or_return!(cursor.read_u8());
     Ok(ConnectedPong {
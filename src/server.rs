@@ -1,19 +1,77 @@
 use std::collections::HashMap;
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Arc,
+};
 use tokio::net::UdpSocket;
 use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::{Mutex, Notify};
 
-use crate::error::{RaknetError, Result};
+use crate::arq::{RecvQ, ReorderThreshold, RtoOptions, SendQ};
+use crate::ban::BanList;
+use crate::congestion::*;
+use crate::error::{DisconnectReason, RaknetError, Result};
+use crate::fragment::FragmentLimits;
 use crate::packet::*;
+use crate::stats::{ListenerStats, StatCounters};
 use crate::utils::*;
 use crate::{raknet_log_debug, raknet_log_error, socket::*};
 
 const SERVER_NAME: &str = "Rust Raknet Server";
 const MAX_CONNECTION: u32 = 99999;
 
-type SessionSender = (i64, Sender<Vec<u8>>);
+// last receive tick , datagram sender , why the session closed , whether the peer finished the handshake
+type SessionSender = (i64, Sender<Vec<u8>>, Arc<DisconnectCell>, Arc<AtomicBool>);
+type CongestionControllerFactory = Arc<dyn Fn() -> Box<dyn CongestionController> + Send + Sync>;
+
+// fill the player counts of a minecraft motd with the live values , other motds are returned as is
+fn live_motd(motd: &str, online: usize, max_sessions: Option<usize>) -> String {
+    let mut fields: Vec<String> = motd.split(';').map(|x| x.to_string()).collect();
+    if fields.len() < 6 || (fields[0] != "MCPE" && fields[0] != "MCEE") {
+        return motd.to_string();
+    }
+    fields[4] = online.to_string();
+    if let Some(max) = max_sessions {
+        fields[5] = max.to_string();
+    }
+    fields.join(";")
+}
+
+// sessions which finished the handshake , half open ones do not use up a slot
+fn connected_sessions(sessions: &HashMap<SocketAddr, SessionSender>) -> usize {
+    sessions
+        .values()
+        .filter(|x| x.3.load(Ordering::Relaxed))
+        .count()
+}
+
+// answer a handshake packet with a refusal such as AlreadyConnected
+async fn send_refusal(socket: &UdpSocket, addr: &SocketAddr, packet: &[u8]) {
+    match socket.send_to(packet, addr).await {
+        Ok(_) => {}
+        Err(e) => {
+            raknet_log_error!("udp socket send_to error : {}", e);
+        }
+    };
+}
+
+// answer ConnectionBanned to a banned peer , return true if it is banned
+async fn refuse_banned(
+    socket: &UdpSocket,
+    addr: &SocketAddr,
+    guid: u64,
+    ban_list: &std::sync::Mutex<BanList>,
+) -> bool {
+    if !ban_list.lock().unwrap().is_banned(&addr.ip()) {
+        return false;
+    }
+    raknet_log_debug!("banned : {}", addr);
+    let packet = write_packet_connection_banned(&ConnectionBanned { magic: true, guid }).unwrap();
+    send_refusal(socket, addr, &packet).await;
+    true
+}
 
 /// Implementation of Raknet Server.
 pub struct RaknetListener {
@@ -28,6 +86,20 @@ pub struct RaknetListener {
     all_session_closed_notifier: Arc<Notify>,
     drop_notifier: Arc<Notify>,
     version_map: Arc<Mutex<HashMap<String, u8>>>,
+    congestion_controller: CongestionControllerFactory,
+    fragment_limits: FragmentLimits,
+    recv_window: usize,
+    recv_buffer: Option<(usize, RecvOverflowPolicy)>,
+    reorder_threshold: ReorderThreshold,
+    rto_options: RtoOptions,
+    // max retransmissions , max unacked milliseconds
+    retransmission_limit: (u32, i64),
+    keepalive_interval: i64,
+    max_mtu: u16,
+    max_sessions: Option<usize>,
+    ban_list: Arc<std::sync::Mutex<BanList>>,
+    // sum of the statistics of all the sessions
+    stats: Arc<StatCounters>,
 }
 
 impl RaknetListener {
@@ -47,6 +119,11 @@ impl RaknetListener {
             }
         };
 
+        Ok(Self::new(s).await)
+    }
+
+    // shared by bind and from_std , every option of the listener starts with its default here
+    async fn new(s: UdpSocket) -> Self {
         let (connection_sender, connection_receiver) = channel::<RaknetSocket>(10);
 
         let ret = Self {
@@ -61,10 +138,25 @@ impl RaknetListener {
             all_session_closed_notifier: Arc::new(Notify::new()),
             drop_notifier: Arc::new(Notify::new()),
             version_map: Arc::new(Mutex::new(HashMap::new())),
+            congestion_controller: Arc::new(|| Box::new(SlidingWindow::new())),
+            fragment_limits: FragmentLimits::default(),
+            recv_window: RecvQ::DEFAULT_WINDOW_BYTES,
+            recv_buffer: None,
+            reorder_threshold: ReorderThreshold::default(),
+            rto_options: RtoOptions::default(),
+            retransmission_limit: (
+                SendQ::DEFAULT_MAX_RETRANSMISSIONS,
+                SendQ::DEFAULT_MAX_UNACKED_MILLIS,
+            ),
+            keepalive_interval: DEFAULT_KEEPALIVE_INTERVAL,
+            max_mtu: RAKNET_MAX_MTU,
+            max_sessions: None,
+            ban_list: Arc::new(std::sync::Mutex::new(BanList::new())),
+            stats: Arc::new(StatCounters::new(None)),
         };
 
         ret.drop_watcher().await;
-        Ok(ret)
+        ret
     }
 
     /// Creates a new RaknetListener from a UdpSocket.
@@ -85,24 +177,7 @@ impl RaknetListener {
             }
         };
 
-        let (connection_sender, connection_receiver) = channel::<RaknetSocket>(10);
-
-        let ret = Self {
-            motd: String::new(),
-            socket: Some(Arc::new(s)),
-            guid: rand::random(),
-            listened: false,
-            connection_receiver,
-            connection_sender,
-            sessions: Arc::new(Mutex::new(HashMap::new())),
-            close_notifier: Arc::new(tokio::sync::Semaphore::new(0)),
-            all_session_closed_notifier: Arc::new(Notify::new()),
-            drop_notifier: Arc::new(Notify::new()),
-            version_map: Arc::new(Mutex::new(HashMap::new())),
-        };
-
-        ret.drop_watcher().await;
-        Ok(ret)
+        Ok(Self::new(s).await)
     }
 
     async fn start_session_collect(
@@ -151,12 +226,9 @@ impl RaknetListener {
             let mut sessions = sessions.lock().await;
 
             for i in sessions.iter() {
-                if i.1
-                     .1
-                    .send(vec![PacketID::Disconnect.to_u8()])
-                    .await
-                    .is_ok()
-                {}
+                let (_, sender, disconnect_reason, _) = i.1;
+                disconnect_reason.set(DisconnectReason::ListenerShutdown);
+                let _ = sender.send(vec![PacketID::Disconnect.to_u8()]).await;
 
                 match socket.send_to(&[PacketID::Disconnect.to_u8()], i.0).await {
                     Ok(_) => {}
@@ -211,7 +283,7 @@ impl RaknetListener {
         if self.motd.is_empty() {
             self.set_motd(
                 SERVER_NAME,
-                MAX_CONNECTION,
+                self.max_sessions.map_or(MAX_CONNECTION, |x| x as u32),
                 "486",
                 "1.18.11",
                 "Survival",
@@ -236,8 +308,20 @@ impl RaknetListener {
         let local_addr = socket.local_addr().unwrap();
         let close_notify = self.close_notifier.clone();
         let version_map = self.version_map.clone();
+        let congestion_controller = self.congestion_controller.clone();
+        let fragment_limits = self.fragment_limits.clone();
+        let recv_window = self.recv_window;
+        let recv_buffer = self.recv_buffer;
+        let reorder_threshold = self.reorder_threshold.clone();
+        let rto_options = self.rto_options.clone();
+        let retransmission_limit = self.retransmission_limit;
+        let keepalive_interval = self.keepalive_interval;
+        let max_mtu = self.max_mtu;
+        let max_sessions = self.max_sessions;
+        let ban_list = self.ban_list.clone();
+        let stats = self.stats.clone();
         tokio::spawn(async move {
-            let mut buf = [0u8; 2048];
+            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
 
             raknet_log_debug!("start listen worker : {}", local_addr);
 
@@ -280,11 +364,13 @@ impl RaknetListener {
                             Err(_) => continue,
                         };
 
+                        let online = connected_sessions(&*sessions.lock().await);
+
                         let packet = crate::packet::PacketUnconnectedPong {
                             time: cur_timestamp_millis(),
                             guid,
                             magic: true,
-                            motd,
+                            motd: live_motd(&motd, online, max_sessions),
                         };
 
                         let pong = match write_packet_pong(&packet) {
@@ -306,11 +392,17 @@ impl RaknetListener {
                             Err(_) => continue,
                         };
 
+                        // this ping only asks servers with open connections
+                        let online = connected_sessions(&*sessions.lock().await);
+                        if matches!(max_sessions, Some(x) if online >= x) {
+                            continue;
+                        }
+
                         let packet = crate::packet::PacketUnconnectedPong {
                             time: cur_timestamp_millis(),
                             guid,
                             magic: true,
-                            motd,
+                            motd: live_motd(&motd, online, max_sessions),
                         };
 
                         let pong = match write_packet_pong(&packet) {
@@ -332,6 +424,10 @@ impl RaknetListener {
                             Err(_) => continue,
                         };
 
+                        if refuse_banned(&socket, &addr, guid, &ban_list).await {
+                            continue;
+                        }
+
                         if !RAKNET_PROTOCOL_VERSION_LIST
                             .as_slice()
                             .contains(&req.protocol_version)
@@ -361,8 +457,9 @@ impl RaknetListener {
                             guid,
                             // Make sure this is false, it is vital for the login sequence to continue
                             use_encryption: 0x00,
-                            // see Open Connection Request 1
-                            mtu_size: RAKNET_CLIENT_MTU,
+                            // the padded request1 was received whole , so the path carries at least its size
+                            mtu_size: ((size + udp_overhead(&addr)) as u16)
+                                .clamp(RAKNET_MIN_MTU, max_mtu),
                         };
 
                         let reply = match write_packet_connection_open_reply_1(&packet) {
@@ -384,11 +481,17 @@ impl RaknetListener {
                             Err(_) => continue,
                         };
 
+                        if refuse_banned(&socket, &addr, guid, &ban_list).await {
+                            continue;
+                        }
+
+                        let mtu = req.mtu.clamp(RAKNET_MIN_MTU, max_mtu);
+
                         let packet = crate::packet::OpenConnectionReply2 {
                             magic: true,
                             guid,
                             address: addr,
-                            mtu: req.mtu,
+                            mtu,
                             encryption_enabled: 0x00,
                         };
 
@@ -397,6 +500,7 @@ impl RaknetListener {
                             Err(_) => continue,
                         };
 
+                        let session_map = sessions.clone();
                         let mut sessions = sessions.lock().await;
 
                         if sessions.contains_key(&addr) {
@@ -405,14 +509,17 @@ impl RaknetListener {
                                 guid,
                             })
                             .unwrap();
+                            send_refusal(&socket, &addr, &packet).await;
+                            continue;
+                        }
 
-                            match socket.send_to(&packet, addr).await {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    raknet_log_error!("udp socket send_to error : {}", e);
-                                }
-                            };
-
+                        if matches!(max_sessions, Some(x) if connected_sessions(&sessions) >= x) {
+                            raknet_log_debug!("no free incoming connections : {}", addr);
+                            let packet = write_packet_no_free_incoming_connections(
+                                &NoFreeIncomingConnections { magic: true, guid },
+                            )
+                            .unwrap();
+                            send_refusal(&socket, &addr, &packet).await;
                             continue;
                         }
 
@@ -433,19 +540,61 @@ impl RaknetListener {
                                 .unwrap_or(&RAKNET_PROTOCOL_VERSION);
                         }
 
-                        let s = RaknetSocket::from(
+                        let s = RaknetSocket::from_listener(
                             &addr,
                             &socket,
                             receiver,
-                            req.mtu,
+                            mtu,
                             collect_sender.clone(),
                             raknet_version,
+                            stats.clone(),
                         )
                         .await;
+                        s.set_keepalive_interval(keepalive_interval);
+                        s.set_congestion_controller(congestion_controller()).await;
+                        s.set_fragment_limits(fragment_limits.clone()).await;
+                        s.set_recv_window(recv_window).await;
+                        if let Some((max_bytes, policy)) = recv_buffer {
+                            s.set_recv_buffer(max_bytes, policy).await;
+                        }
+                        s.set_reorder_threshold(reorder_threshold.clone()).await;
+                        s.set_rto_options(rto_options.clone()).await;
+                        s.set_retransmission_limit(retransmission_limit.0, retransmission_limit.1)
+                            .await;
 
                         raknet_log_debug!("accept connection : {}", addr);
-                        sessions.insert(addr, (cur_timestamp_millis(), sender));
-                        let _ = connection_sender.send(s).await;
+                        let connected = Arc::new(AtomicBool::new(false));
+                        sessions.insert(
+                            addr,
+                            (
+                                cur_timestamp_millis(),
+                                sender,
+                                s.disconnect_cell(),
+                                connected.clone(),
+                            ),
+                        );
+
+                        // hand the socket to accept() once the peer finished the handshake ,
+                        // data is sent without waiting for acks so the application could otherwise send and close before the peer is connected
+                        let connection_sender = connection_sender.clone();
+                        tokio::spawn(async move {
+                            if !s.wait_incomming().await {
+                                return;
+                            }
+                            {
+                                // peers handshaking at the same time all passed the check of request2
+                                let sessions = session_map.lock().await;
+                                if matches!(max_sessions, Some(x) if connected_sessions(&sessions) >= x)
+                                {
+                                    raknet_log_debug!("no free incoming connections : {}", addr);
+                                    std::mem::drop(sessions);
+                                    let _ = s.close().await;
+                                    return;
+                                }
+                                connected.store(true, Ordering::Relaxed);
+                            }
+                            let _ = connection_sender.send(s).await;
+                        });
                     }
                     PacketID::Disconnect => {
                         let mut sessions = sessions.lock().await;
@@ -475,6 +624,8 @@ impl RaknetListener {
 
     /// Waiting for and receiving new Raknet connections, returning a Raknet socket
     ///
+    /// The socket is returned once the peer has finished the connection handshake.
+    ///
     /// Call this method must be after calling RaknetListener::listen()
     ///
     /// # Example
@@ -594,6 +745,288 @@ impl RaknetListener {
         Ok(())
     }
 
+    /// Set the congestion controller of the accepted connections, the default one is SlidingWindow.
+    ///
+    /// Call this method must be before calling RaknetListener::listen()
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.set_congestion_controller(|| Box::new(Cubic::new()));
+    /// listener.listen().await;
+    /// ```
+    pub fn set_congestion_controller<F>(&mut self, factory: F)
+    where
+        F: Fn() -> Box<dyn CongestionController> + Send + Sync + 'static,
+    {
+        self.congestion_controller = Arc::new(factory);
+    }
+
+    /// Set the limits of the packets being reassembled from fragments on the accepted connections.
+    /// A peer exceeding them is disconnected.
+    ///
+    /// Call this method must be before calling RaknetListener::listen()
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.set_fragment_limits(FragmentLimits {
+    ///     max_compounds: 4,
+    ///     ..FragmentLimits::default()
+    /// });
+    /// listener.listen().await;
+    /// ```
+    pub fn set_fragment_limits(&mut self, limits: FragmentLimits) {
+        self.fragment_limits = limits;
+    }
+
+    /// Set the maximum of bytes of the ordered packets waiting for a missing one on the accepted connections.
+    /// Datagrams beyond the receive window are dropped and nacked, a peer which keeps sending them is disconnected.
+    ///
+    /// Call this method must be before calling RaknetListener::listen()
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.set_recv_window(1024 * 1024);
+    /// listener.listen().await;
+    /// ```
+    pub fn set_recv_window(&mut self, max_bytes: usize) {
+        self.recv_window = max_bytes;
+    }
+
+    /// Set the buffer of the packets received but not read by `recv` yet on the accepted connections, see `RaknetSocket::set_recv_buffer`.
+    ///
+    /// Call this method must be before calling RaknetListener::listen()
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.set_recv_buffer(1024 * 1024, RecvOverflowPolicy::Disconnect);
+    /// listener.listen().await;
+    /// ```
+    pub fn set_recv_buffer(&mut self, max_bytes: usize, policy: RecvOverflowPolicy) {
+        self.recv_buffer = Some((max_bytes, policy));
+    }
+
+    /// Set how much reordering the accepted connections tolerate before they consider a datagram lost.
+    ///
+    /// Call this method must be before calling RaknetListener::listen()
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.set_reorder_threshold(ReorderThreshold { packets: 8, millis: 50 });
+    /// listener.listen().await;
+    /// ```
+    pub fn set_reorder_threshold(&mut self, threshold: ReorderThreshold) {
+        self.reorder_threshold = threshold;
+    }
+
+    /// Set the constants of the retransmission timeout estimator (RFC 6298) of the accepted connections.
+    ///
+    /// Call this method must be before calling RaknetListener::listen()
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.set_rto_options(RtoOptions {
+    ///     min_rto: 200,
+    ///     ..RtoOptions::default()
+    /// });
+    /// listener.listen().await;
+    /// ```
+    pub fn set_rto_options(&mut self, options: RtoOptions) {
+        self.rto_options = options;
+    }
+
+    /// Set when the peers of the accepted connections are considered dead, see `RaknetSocket::set_retransmission_limit`.
+    ///
+    /// Call this method must be before calling RaknetListener::listen()
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.set_retransmission_limit(8, 3000);
+    /// listener.listen().await;
+    /// ```
+    pub fn set_retransmission_limit(&mut self, max_retransmissions: u32, max_unacked_millis: i64) {
+        self.retransmission_limit = (max_retransmissions, max_unacked_millis);
+    }
+
+    /// Set the maximum number of sessions which finished the handshake (default unlimited).
+    ///
+    /// When it is reached new peers are refused with NoFreeIncomingConnections and the unconnected pings asking for open connections are not answered.
+    /// Peers which were still in the handshake when it was reached are disconnected once they finish it.
+    /// It also replaces the max player count of a minecraft motd.
+    ///
+    /// Call this method must be before calling RaknetListener::listen()
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.set_max_sessions(100);
+    /// listener.listen().await;
+    /// ```
+    pub fn set_max_sessions(&mut self, max_sessions: usize) {
+        self.max_sessions = Some(max_sessions);
+    }
+
+    /// Set the largest mtu accepted in the handshake, ip and udp headers included (default 1492).
+    ///
+    /// Values below 576 are raised to 576.
+    ///
+    /// Call this method must be before calling RaknetListener::listen()
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.set_max_mtu(1200);
+    /// listener.listen().await;
+    /// ```
+    pub fn set_max_mtu(&mut self, max_mtu: u16) {
+        self.max_mtu = max_mtu.max(RAKNET_MIN_MTU);
+    }
+
+    /// Set the milliseconds between two connected pings sent on the accepted connections, see `RaknetSocket::set_keepalive_interval`.
+    ///
+    /// Call this method must be before calling RaknetListener::listen()
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.set_keepalive_interval(1000);
+    /// listener.listen().await;
+    /// ```
+    pub fn set_keepalive_interval(&mut self, millis: i64) {
+        self.keepalive_interval = millis;
+    }
+
+    /// Disconnect a session, returns false if there is no session with the peer.
+    ///
+    /// `RaknetSocket::disconnect_reason` of the session becomes DisconnectReason::Kicked.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.listen().await;
+    /// let socket = listener.accept().await.unwrap();
+    /// listener.kick(&socket.peer_addr().unwrap()).await;
+    /// ```
+    pub async fn kick(&self, peer: &SocketAddr) -> bool {
+        let sessions = self.sessions.lock().await;
+        match sessions.get(peer) {
+            Some((_, sender, disconnect_reason, _)) => {
+                disconnect_reason.set(DisconnectReason::Kicked);
+                // the session closes as if the peer had disconnected , and notifies the peer
+                let _ = sender.send(vec![PacketID::Disconnect.to_u8()]).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ban an address or a cidr range such as `192.168.0.0/16`, forever if `duration` is None.
+    ///
+    /// Banned peers get a ConnectionBanned reply to the handshake, and their sessions are kicked.
+    /// Banning the same range again replaces its duration.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.listen().await;
+    /// listener.ban("10.0.0.0/8", Some(std::time::Duration::from_secs(3600))).await.unwrap();
+    /// ```
+    pub async fn ban(&self, ip_or_cidr: &str, duration: Option<std::time::Duration>) -> Result<()> {
+        self.ban_list.lock().unwrap().ban(ip_or_cidr, duration)?;
+        self.kick_banned().await;
+        Ok(())
+    }
+
+    /// Remove a ban added with the same address or cidr range, returns false if there was none.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.ban("10.0.0.0/8", None).await.unwrap();
+    /// assert!(listener.unban("10.0.0.0/8").unwrap());
+    /// ```
+    pub fn unban(&self, ip_or_cidr: &str) -> Result<bool> {
+        self.ban_list.lock().unwrap().unban(ip_or_cidr)
+    }
+
+    /// Returns true if the address is in a banned range which has not expired.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.ban("10.0.0.0/8", None).await.unwrap();
+    /// assert!(listener.is_banned(&"10.1.2.3".parse().unwrap()));
+    /// ```
+    pub fn is_banned(&self, ip: &IpAddr) -> bool {
+        self.ban_list.lock().unwrap().is_banned(ip)
+    }
+
+    /// Export the bans which have not expired, one per line with the unix timestamp in milliseconds it expires at or `never`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// std::fs::write("bans.txt", listener.export_bans()).unwrap();
+    /// ```
+    pub fn export_bans(&self) -> String {
+        self.ban_list.lock().unwrap().export()
+    }
+
+    /// Import bans exported by `RaknetListener::export_bans`, returns the count of imported lines.
+    ///
+    /// Nothing is imported if a line is incorrect, RaknetError::IncorrectBanList then holds its line number.
+    /// Sessions of the imported ranges are kicked.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.import_bans(&std::fs::read_to_string("bans.txt").unwrap()).await.unwrap();
+    /// listener.listen().await;
+    /// ```
+    pub async fn import_bans(&self, bans: &str) -> Result<usize> {
+        let count = self.ban_list.lock().unwrap().import(bans)?;
+        self.kick_banned().await;
+        Ok(count)
+    }
+
+    async fn kick_banned(&self) {
+        let banned: Vec<SocketAddr> = {
+            let sessions = self.sessions.lock().await;
+            let mut ban_list = self.ban_list.lock().unwrap();
+            sessions
+                .keys()
+                .filter(|x| ban_list.is_banned(&x.ip()))
+                .copied()
+                .collect()
+        };
+        for addr in banned {
+            raknet_log_debug!("kick banned session : {}", addr);
+            self.kick(&addr).await;
+        }
+    }
+
+    /// Returns a snapshot of the statistics of all the sessions of this listener.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut listener = RaknetListener::bind("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// listener.listen().await;
+    /// let stats = listener.stats().await;
+    /// println!("sessions : {} , sent : {} bytes", stats.sessions, stats.traffic.bytes_sent);
+    /// ```
+    pub async fn stats(&self) -> ListenerStats {
+        ListenerStats {
+            sessions: connected_sessions(&*self.sessions.lock().await),
+            traffic: self.stats.snapshot(),
+        }
+    }
+
     pub async fn get_peer_raknet_version(&self, peer: &SocketAddr) -> Result<u8> {
         let version_map = self.version_map.lock().await;
         let ver = version_map.get(&peer.to_string());
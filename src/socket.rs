@@ -1,5 +1,6 @@
 use rand::Rng;
 use std::{
+    collections::VecDeque,
     net::SocketAddr,
     sync::{
         atomic::{AtomicI64, AtomicU8},
@@ -8,33 +9,321 @@ use std::{
 };
 use tokio::{
     net::UdpSocket,
-    sync::{mpsc::channel, Mutex, Notify, RwLock},
+    sync::{mpsc::channel, oneshot, Mutex, Notify, RwLock, RwLockWriteGuard},
     time::{sleep, timeout},
 };
 
 use crate::{
-    error::{RaknetError, Result},
+    error::{ConnectStage, DisconnectReason, RaknetError, Result},
     raknet_log_error, raknet_log_info,
 };
 use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::mpsc::{Receiver, Sender};
 
-use crate::{arq::*, packet::*, raknet_log_debug, utils::*};
+use crate::{
+    arq::*,
+    congestion::CongestionController,
+    fragment::FragmentLimits,
+    packet::*,
+    raknet_log_debug,
+    stats::{RaknetStats, StatCounters},
+    utils::*,
+};
+
+/// Information about a packet received by `RaknetSocket::recv_with_info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecvInfo {
+    /// reliability the packet was sent with
+    pub reliability: Reliability,
+    /// ordering channel the packet was sent on, always 0 for unordered packets
+    pub order_channel: u8,
+}
+
+// why the connection closed , the first reason set wins
+pub(crate) struct DisconnectCell {
+    reason: std::sync::Mutex<Option<DisconnectReason>>,
+    // the peer sent a disconnect notification , even after another reason was set
+    remote_disconnected: AtomicBool,
+}
+
+impl DisconnectCell {
+    fn new() -> Self {
+        Self {
+            reason: std::sync::Mutex::new(None),
+            remote_disconnected: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn set(&self, reason: DisconnectReason) {
+        if reason == DisconnectReason::RemoteDisconnect {
+            self.remote_disconnected.store(true, Ordering::Relaxed);
+        }
+        let mut r = self.reason.lock().unwrap();
+        if r.is_none() {
+            *r = Some(reason);
+        }
+    }
+
+    fn get(&self) -> Option<DisconnectReason> {
+        *self.reason.lock().unwrap()
+    }
+
+    fn remote_disconnected(&self) -> bool {
+        self.remote_disconnected.load(Ordering::Relaxed)
+    }
+
+    // the error returned by the methods of a closed connection
+    fn error(&self) -> RaknetError {
+        match self.get() {
+            Some(DisconnectReason::ConnectionLost) => RaknetError::ConnectionLost,
+            Some(r) => RaknetError::ConnectionClosed(r),
+            None => RaknetError::ConnectionClosed(DisconnectReason::LocalClose),
+        }
+    }
+}
+
+/// Receipt of a packet sent by `RaknetSocket::send_with_receipt`.
+pub struct AckReceipt {
+    receiver: oneshot::Receiver<bool>,
+    close_notifier: Arc<tokio::sync::Semaphore>,
+    disconnect_reason: Arc<DisconnectCell>,
+}
+
+impl AckReceipt {
+    /// Wait until the peer acknowledged every datagram carrying the packet.
+    ///
+    /// Returns RaknetError::PacketLost if a packet sent with Reliability::UnreliableWithAckReceipt was lost,
+    /// and RaknetError::ConnectionClosed or RaknetError::ConnectionLost if the connection closed first.
+    /// The error carries the same reason as `RaknetSocket::disconnect_reason`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let receipt = socket.send_with_receipt(&[0xfe], Reliability::ReliableWithAckReceipt).await.unwrap();
+    /// receipt.wait().await.unwrap();
+    /// ```
+    pub async fn wait(self) -> Result<()> {
+        let close_notifier = self.close_notifier;
+        let disconnect_reason = self.disconnect_reason;
+        tokio::select! {
+            biased;
+            a = self.receiver => match a {
+                Ok(true) => Ok(()),
+                Ok(false) => Err(RaknetError::PacketLost),
+                Err(_) => Err(disconnect_reason.error()),
+            },
+            _ = close_notifier.acquire() => Err(disconnect_reason.error()),
+        }
+    }
+}
+
+/// What a connection does with a received packet when its receive buffer is full, see `RaknetSocket::set_recv_buffer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecvOverflowPolicy {
+    /// drop the received packet
+    DropNewest,
+    /// drop the oldest packets in the buffer to make room for the received one
+    DropOldest,
+    /// close the connection
+    Disconnect,
+}
+
+// packet delivered to the application with its receive metadata
+type UserPacket = (Vec<u8>, RecvInfo);
+
+// packets received but not read by the application yet , the receiver never waits for the application
+struct RecvBuffer {
+    packets: VecDeque<UserPacket>,
+    bytes: usize,
+    max_bytes: usize,
+    policy: RecvOverflowPolicy,
+    // the receiver has finished , no more packets will come
+    finished: bool,
+}
+
+impl RecvBuffer {
+    const DEFAULT_MAX_BYTES: usize = 8 * 1024 * 1024;
+
+    fn new() -> Self {
+        Self {
+            packets: VecDeque::new(),
+            bytes: 0,
+            max_bytes: RecvBuffer::DEFAULT_MAX_BYTES,
+            policy: RecvOverflowPolicy::Disconnect,
+            finished: false,
+        }
+    }
+
+    // return false if the connection should be closed
+    fn push(&mut self, packet: UserPacket) -> bool {
+        // a packet larger than the buffer is still accepted by an empty buffer
+        if !self.packets.is_empty() && self.bytes + packet.0.len() > self.max_bytes {
+            match self.policy {
+                RecvOverflowPolicy::DropNewest => {
+                    raknet_log_debug!("recv buffer is full , drop the received packet");
+                    return true;
+                }
+                RecvOverflowPolicy::DropOldest => {
+                    while !self.packets.is_empty() && self.bytes + packet.0.len() > self.max_bytes {
+                        raknet_log_debug!("recv buffer is full , drop the oldest packet");
+                        self.pop();
+                    }
+                }
+                RecvOverflowPolicy::Disconnect => {
+                    raknet_log_error!("recv buffer is full , disconnect");
+                    return false;
+                }
+            }
+        }
+        self.bytes += packet.0.len();
+        self.packets.push_back(packet);
+        true
+    }
+
+    fn pop(&mut self) -> Option<UserPacket> {
+        let packet = self.packets.pop_front()?;
+        self.bytes -= packet.0.len();
+        Some(packet)
+    }
+}
+
+// round trip times measured with connected pings
+struct PingState {
+    // -1 before the first pong
+    last_rtt: AtomicI64,
+    // clock of the peer minus the local clock
+    clock_offset: AtomicI64,
+    // client timestamp of the newest pong , -1 before the first pong
+    last_pong: AtomicI64,
+    pong_notifier: Notify,
+}
+
+impl PingState {
+    fn new() -> Self {
+        Self {
+            last_rtt: AtomicI64::new(-1),
+            clock_offset: AtomicI64::new(0),
+            last_pong: AtomicI64::new(-1),
+            pong_notifier: Notify::new(),
+        }
+    }
+
+    fn on_pong(&self, pong: &ConnectedPong, tick: i64) {
+        // a pong from the future or older than the newest one tells nothing new
+        if pong.client_timestamp > tick
+            || pong.client_timestamp <= self.last_pong.load(Ordering::Relaxed)
+        {
+            return;
+        }
+        let rtt = tick - pong.client_timestamp;
+        // the peer stamped the pong about half a round trip after the ping was sent
+        self.clock_offset.store(
+            pong.server_timestamp - (pong.client_timestamp + rtt / 2),
+            Ordering::Relaxed,
+        );
+        self.last_rtt.store(rtt, Ordering::Relaxed);
+        self.last_pong
+            .store(pong.client_timestamp, Ordering::Relaxed);
+        self.pong_notifier.notify_waiters();
+    }
+}
+
+// the error of a reply refusing the connection , none if buf is not one
+fn read_refusal(buf: &[u8]) -> Option<RaknetError> {
+    let (magic, error) = match PacketID::from(buf[0]) {
+        Ok(PacketID::AlreadyConnected) => (
+            read_packet_already_connected(buf).map(|p| p.magic),
+            RaknetError::AlreadyConnected,
+        ),
+        Ok(PacketID::NoFreeIncomingConnections) => (
+            read_packet_no_free_incoming_connections(buf).map(|p| p.magic),
+            RaknetError::NoFreeIncomingConnections,
+        ),
+        Ok(PacketID::ConnectionBanned) => (
+            read_packet_connection_banned(buf).map(|p| p.magic),
+            RaknetError::ConnectionBanned,
+        ),
+        _ => return None,
+    };
+    match magic {
+        Ok(true) => Some(error),
+        _ => {
+            raknet_log_debug!("incorrect refusal packet");
+            None
+        }
+    }
+}
+
+/// Retry policy of `RaknetSocket::connect_with_options`.
+#[derive(Clone, Debug)]
+pub struct ConnectOptions {
+    /// Default is RAKNET_PROTOCOL_VERSION.
+    pub raknet_version: u8,
+    /// Deadline of the whole handshake. Default is 10s.
+    pub timeout: std::time::Duration,
+    /// Wait for a reply before sending a request again. Default is 1s.
+    pub attempt_timeout: std::time::Duration,
+    /// Requests sent at each stage of the handshake before giving up. Default is 10.
+    pub max_attempts: u32,
+    /// The attempt timeout is multiplied by this after every attempt. Default is 1.0.
+    pub backoff: f64,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            raknet_version: RAKNET_PROTOCOL_VERSION,
+            timeout: std::time::Duration::from_secs(10),
+            attempt_timeout: std::time::Duration::from_secs(1),
+            max_attempts: 10,
+            backoff: 1.0,
+        }
+    }
+}
+
+impl ConnectOptions {
+    // wait of the attempt-th request , none when the stage or the whole handshake is out of time
+    fn attempt_wait(
+        &self,
+        attempt: u32,
+        deadline: tokio::time::Instant,
+    ) -> Option<std::time::Duration> {
+        let remaining = deadline.checked_duration_since(tokio::time::Instant::now())?;
+        if attempt >= self.max_attempts || remaining.is_zero() {
+            return None;
+        }
+        let factor = self.backoff.max(1.0).powi(attempt as i32);
+        let wait =
+            std::time::Duration::try_from_secs_f64(self.attempt_timeout.as_secs_f64() * factor)
+                .unwrap_or(remaining);
+        Some(wait.min(remaining))
+    }
+}
 
 /// Raknet socket wrapper with local and remote.
 pub struct RaknetSocket {
     local_addr: SocketAddr,
     peer_addr: SocketAddr,
-    user_data_receiver: Arc<Mutex<Receiver<Vec<u8>>>>,
+    recv_buffer: Arc<Mutex<RecvBuffer>>,
+    recv_notifier: Arc<Notify>,
     recvq: Arc<Mutex<RecvQ>>,
     sendq: Arc<RwLock<SendQ>>,
     close_notifier: Arc<tokio::sync::Semaphore>,
+    disconnect_reason: Arc<DisconnectCell>,
     last_heartbeat_time: Arc<AtomicI64>,
     enable_loss: Arc<AtomicBool>,
     loss_rate: Arc<AtomicU8>,
     incomming_notifier: Arc<Notify>,
-    sender: Sender<(Vec<u8>, SocketAddr, bool, u8)>,
+    flush_notifier: Arc<Notify>,
+    // woken up when acks or packing free space in the send buffer
+    send_buffer_notifier: Arc<Notify>,
     drop_notifier: Arc<Notify>,
+    ping_state: Arc<PingState>,
+    stats: Arc<StatCounters>,
+    // milliseconds between two connected pings , 0 disables them
+    keepalive_interval: Arc<AtomicI64>,
+    // close_graceful was called , new packets are refused
+    closing: AtomicBool,
     raknet_version: u8,
 }
 
@@ -50,39 +339,80 @@ impl RaknetSocket {
         collecter: Arc<Mutex<Sender<SocketAddr>>>,
         raknet_version: u8,
     ) -> Self {
-        let (user_data_sender, user_data_receiver) = channel::<Vec<u8>>(100);
-        let (sender_sender, sender_receiver) = channel::<(Vec<u8>, SocketAddr, bool, u8)>(10);
+        RaknetSocket::from_listener(
+            addr,
+            s,
+            receiver,
+            mtu,
+            collecter,
+            raknet_version,
+            Arc::new(StatCounters::new(None)),
+        )
+        .await
+    }
 
+    // same as `from` , the statistics of the connection are added to the ones of the listener
+    pub(crate) async fn from_listener(
+        addr: &SocketAddr,
+        s: &Arc<UdpSocket>,
+        receiver: Receiver<Vec<u8>>,
+        mtu: u16,
+        collecter: Arc<Mutex<Sender<SocketAddr>>>,
+        raknet_version: u8,
+        listener_stats: Arc<StatCounters>,
+    ) -> Self {
+        let mut sendq = SendQ::new(mtu);
+        sendq.set_udp_overhead(udp_overhead(addr));
         let ret = RaknetSocket {
             peer_addr: *addr,
             local_addr: s.local_addr().unwrap(),
-            user_data_receiver: Arc::new(Mutex::new(user_data_receiver)),
+            recv_buffer: Arc::new(Mutex::new(RecvBuffer::new())),
+            recv_notifier: Arc::new(Notify::new()),
             recvq: Arc::new(Mutex::new(RecvQ::new())),
-            sendq: Arc::new(RwLock::new(SendQ::new(mtu))),
+            sendq: Arc::new(RwLock::new(sendq)),
             close_notifier: Arc::new(tokio::sync::Semaphore::new(0)),
+            disconnect_reason: Arc::new(DisconnectCell::new()),
             last_heartbeat_time: Arc::new(AtomicI64::new(cur_timestamp_millis())),
             enable_loss: Arc::new(AtomicBool::new(false)),
             loss_rate: Arc::new(AtomicU8::new(0)),
             incomming_notifier: Arc::new(Notify::new()),
-            sender: sender_sender,
+            flush_notifier: Arc::new(Notify::new()),
+            send_buffer_notifier: Arc::new(Notify::new()),
             drop_notifier: Arc::new(Notify::new()),
+            ping_state: Arc::new(PingState::new()),
+            stats: Arc::new(StatCounters::new(Some(listener_stats))),
+            keepalive_interval: Arc::new(AtomicI64::new(DEFAULT_KEEPALIVE_INTERVAL)),
+            closing: AtomicBool::new(false),
             raknet_version,
         };
-        ret.start_receiver(s, receiver, user_data_sender);
+        ret.start_receiver(s, receiver);
         ret.start_tick(s, Some(collecter));
-        ret.start_sender(s, sender_receiver);
         ret.drop_watcher().await;
         ret
     }
 
+    /// Wait for the peer to finish the connection handshake, return false if the connection closed before.
+    pub(crate) async fn wait_incomming(&self) -> bool {
+        tokio::select! {
+            // a peer which disconnects right after the handshake is still accepted
+            biased;
+            _ = self.incomming_notifier.notified() => true,
+            _ = self.close_notifier.acquire() => false,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn handle(
         frame: &FrameSetPacket,
         peer_addr: &SocketAddr,
         local_addr: &SocketAddr,
         sendq: &RwLock<SendQ>,
-        user_data_sender: &Sender<Vec<u8>>,
+        recv_buffer: &Mutex<RecvBuffer>,
+        recv_notifier: &Notify,
         incomming_notify: &Notify,
-    ) -> Result<bool> {
+        ping_state: &PingState,
+        stats: &StatCounters,
+    ) -> Result<Option<DisconnectReason>> {
         match PacketID::from(frame.data[0])? {
             PacketID::ConnectionRequest => {
                 let packet = read_packet_connection_request(frame.data.as_slice())?;
@@ -126,6 +456,8 @@ impl RaknetSocket {
             }
             PacketID::NewIncomingConnection => {
                 let _packet = read_packet_new_incomming_connection(frame.data.as_slice())?;
+                raknet_log_debug!("incomming notified");
+                incomming_notify.notify_one();
             }
             PacketID::ConnectedPing => {
                 let packet = read_packet_connected_ping(frame.data.as_slice())?;
@@ -138,20 +470,26 @@ impl RaknetSocket {
                 let buf = write_packet_connected_pong(&packet_reply)?;
                 sendq.write().await.insert(Reliability::Unreliable, &buf)?;
             }
-            PacketID::ConnectedPong => {}
+            PacketID::ConnectedPong => {
+                let packet = read_packet_connected_pong(frame.data.as_slice())?;
+                ping_state.on_pong(&packet, cur_timestamp_millis());
+            }
             PacketID::Disconnect => {
-                return Ok(false);
+                return Ok(Some(DisconnectReason::RemoteDisconnect));
             }
             _ => {
-                match user_data_sender.send(frame.data.clone()).await {
-                    Ok(_) => {}
-                    Err(_) => {
-                        return Ok(false);
-                    }
+                let info = RecvInfo {
+                    reliability: frame.reliability()?,
+                    order_channel: frame.order_channel,
                 };
+                stats.on_message_received(&info.reliability);
+                if !recv_buffer.lock().await.push((frame.data.clone(), info)) {
+                    return Ok(Some(DisconnectReason::RecvBufferFull));
+                }
+                recv_notifier.notify_waiters();
             }
         }
-        Ok(true)
+        Ok(None)
     }
 
     async fn sendto(
@@ -160,7 +498,9 @@ impl RaknetSocket {
         target: &SocketAddr,
         enable_loss: bool,
         loss_rate: u8,
+        stats: &StatCounters,
     ) -> tokio::io::Result<usize> {
+        stats.on_datagram_sent(buf.len());
         if enable_loss {
             let mut rng = rand::thread_rng();
             let i: u8 = rng.gen_range(0..11);
@@ -189,47 +529,105 @@ impl RaknetSocket {
     ///    //do something
     /// }
     /// ```
-
     pub async fn connect(addr: &SocketAddr) -> Result<Self> {
-        Self::connect_with_version(addr, RAKNET_PROTOCOL_VERSION).await
+        Self::connect_with_options(addr, ConnectOptions::default()).await
     }
 
     pub async fn connect_with_version(addr: &SocketAddr, raknet_version: u8) -> Result<Self> {
+        Self::connect_with_options(
+            addr,
+            ConnectOptions {
+                raknet_version,
+                ..ConnectOptions::default()
+            },
+        )
+        .await
+    }
+
+    /// Connect to a Raknet server, giving up after `timeout`
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect_timeout("127.0.0.1:19132".parse().unwrap(), std::time::Duration::from_secs(3)).await.unwrap();
+    /// ```
+    pub async fn connect_timeout(addr: &SocketAddr, timeout: std::time::Duration) -> Result<Self> {
+        Self::connect_with_options(
+            addr,
+            ConnectOptions {
+                timeout,
+                ..ConnectOptions::default()
+            },
+        )
+        .await
+    }
+
+    /// Connect to a Raknet server with the retry policy of `options`
+    ///
+    /// Returns `RaknetError::ConnectTimeout` with the stage of the handshake which got no answer.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect_with_options("127.0.0.1:19132".parse().unwrap(), ConnectOptions {
+    ///     timeout: std::time::Duration::from_secs(5),
+    ///     attempt_timeout: std::time::Duration::from_millis(500),
+    ///     backoff: 1.5,
+    ///     ..ConnectOptions::default()
+    /// }).await.unwrap();
+    /// ```
+    pub async fn connect_with_options(addr: &SocketAddr, options: ConnectOptions) -> Result<Self> {
         let guid: u64 = rand::random();
+        let raknet_version = options.raknet_version;
+        let deadline = tokio::time::Instant::now() + options.timeout;
 
-        let s = match UdpSocket::bind("0.0.0.0:0").await {
+        let bind_addr = if addr.is_ipv6() {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let s = match UdpSocket::bind(bind_addr).await {
             Ok(p) => p,
             Err(_) => return Err(RaknetError::BindAdressError),
         };
 
-        let packet = OpenConnectionRequest1 {
-            magic: true,
-            protocol_version: raknet_version,
-            mtu_size: RAKNET_CLIENT_MTU,
-        };
-
-        let buf = write_packet_connection_open_request_1(&packet).unwrap();
-
         let mut remote_addr: SocketAddr;
         let mut reply1_size: usize;
+        // mtu of the request1 the server answered
+        let mut request1_mtu: u16;
 
-        let mut reply1_buf = [0u8; 2048];
+        let mut reply1_buf = vec![0u8; MAX_DATAGRAM_SIZE];
 
+        // mtu discovery , each size is tried a few times before falling back to a smaller one
+        let mut attempt = 0;
         loop {
+            let wait = match options.attempt_wait(attempt, deadline) {
+                Some(p) => p,
+                None => {
+                    return Err(RaknetError::ConnectTimeout(
+                        ConnectStage::OpenConnectionRequest1,
+                    ))
+                }
+            };
+            request1_mtu = RAKNET_MTU_SIZES[(attempt as usize / 3).min(RAKNET_MTU_SIZES.len() - 1)];
+            attempt += 1;
+
+            let packet = OpenConnectionRequest1 {
+                magic: true,
+                protocol_version: raknet_version,
+                // the padding is computed for ipv4 headers
+                mtu_size: request1_mtu - (udp_overhead(addr) - 28) as u16,
+            };
+            let buf = write_packet_connection_open_request_1(&packet).unwrap();
+
             match s.send_to(&buf, addr).await {
                 Ok(p) => p,
                 Err(e) => {
-                    raknet_log_error!("udp socket sendto error {}", e);
-                    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+                    // a datagram larger than the path mtu may be refused locally
+                    raknet_log_debug!("udp socket sendto error {} , mtu {}", e, request1_mtu);
+                    sleep(wait.min(std::time::Duration::from_millis(100))).await;
                     continue;
                 }
             };
-            let (size, src) = match match timeout(
-                std::time::Duration::from_secs(2),
-                s.recv_from(&mut reply1_buf),
-            )
-            .await
-            {
+            let (size, src) = match match timeout(wait, s.recv_from(&mut reply1_buf)).await {
                 Ok(p) => p,
                 Err(_) => {
                     raknet_log_debug!("wait reply1 timeout");
@@ -248,12 +646,15 @@ impl RaknetSocket {
 
             if reply1_buf[0] != PacketID::OpenConnectionReply1.to_u8() {
                 if reply1_buf[0] == PacketID::IncompatibleProtocolVersion.to_u8() {
-                    let _packet = match read_packet_incompatible_protocol_version(&buf[..size]) {
-                        Ok(p) => p,
-                        Err(_) => return Err(RaknetError::NotSupportVersion),
-                    };
+                    let _packet =
+                        match read_packet_incompatible_protocol_version(&reply1_buf[..size]) {
+                            Ok(p) => p,
+                            Err(_) => return Err(RaknetError::NotSupportVersion),
+                        };
 
                     return Err(RaknetError::NotSupportVersion);
+                } else if let Some(e) = read_refusal(&reply1_buf[..size]) {
+                    return Err(e);
                 } else {
                     raknet_log_debug!("incorrect reply1");
                     continue;
@@ -268,47 +669,72 @@ impl RaknetSocket {
             Err(_) => return Err(RaknetError::PacketParseError),
         };
 
+        // the server derived its mtu from one of our requests , never go above what we sent
+        let mtu = reply1
+            .mtu_size
+            .clamp(RAKNET_MIN_MTU, request1_mtu.max(RAKNET_MIN_MTU));
+        raknet_log_debug!("{} , mtu {}", addr, mtu);
+
         let packet = OpenConnectionRequest2 {
             magic: true,
             address: remote_addr,
-            mtu: reply1.mtu_size,
+            mtu,
             guid,
         };
 
         let buf = write_packet_connection_open_request_2(&packet).unwrap();
 
+        let mut attempt = 0;
         loop {
+            let wait = match options.attempt_wait(attempt, deadline) {
+                Some(p) => p,
+                None => {
+                    return Err(RaknetError::ConnectTimeout(
+                        ConnectStage::OpenConnectionRequest2,
+                    ))
+                }
+            };
+            attempt += 1;
+
             match s.send_to(&buf, addr).await {
                 Ok(_) => {}
                 Err(e) => {
                     raknet_log_error!("udp socket sendto error {}", e);
-                    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+                    sleep(wait).await;
                     continue;
                 }
             };
 
-            let mut buf = [0u8; 2048];
-            let (size, _) =
-                match match timeout(std::time::Duration::from_secs(2), s.recv_from(&mut buf)).await
-                {
-                    Ok(p) => p,
-                    Err(_) => {
-                        raknet_log_debug!("wait reply2 timeout");
-                        continue;
-                    }
-                } {
-                    Ok(p) => p,
-                    Err(e) => {
-                        raknet_log_error!("recvfrom error : {}", e);
-                        continue;
-                    }
-                };
+            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+            let (size, _) = match match timeout(wait, s.recv_from(&mut buf)).await {
+                Ok(p) => p,
+                Err(_) => {
+                    raknet_log_debug!("wait reply2 timeout");
+                    continue;
+                }
+            } {
+                Ok(p) => p,
+                Err(e) => {
+                    raknet_log_error!("recvfrom error : {}", e);
+                    continue;
+                }
+            };
 
             if buf[0] == PacketID::OpenConnectionReply1.to_u8() {
                 raknet_log_debug!("repeat receive reply1");
                 continue;
             }
 
+            if buf[0] == PacketID::AlreadyConnected.to_u8() && attempt > 1 {
+                // the session was created by a previous request2 whose reply was lost , the local port is new so nobody else could own it
+                raknet_log_debug!("already connected , reply2 lost");
+                break;
+            }
+
+            if let Some(e) = read_refusal(&buf[..size]) {
+                return Err(e);
+            }
+
             if buf[0] != PacketID::OpenConnectionReply2.to_u8() {
                 raknet_log_debug!("incorrect reply2");
                 continue;
@@ -322,7 +748,9 @@ impl RaknetSocket {
             break;
         }
 
-        let sendq = Arc::new(RwLock::new(SendQ::new(reply1.mtu_size)));
+        let mut sendq = SendQ::new(mtu);
+        sendq.set_udp_overhead(udp_overhead(addr));
+        let sendq = Arc::new(RwLock::new(sendq));
 
         let packet = ConnectionRequest {
             guid,
@@ -336,8 +764,6 @@ impl RaknetSocket {
         sendq1.insert(Reliability::ReliableOrdered, &buf)?;
         std::mem::drop(sendq1);
 
-        let (user_data_sender, user_data_receiver) = channel::<Vec<u8>>(100);
-
         let (sender, receiver) = channel::<Vec<u8>>(100);
 
         let s = Arc::new(s);
@@ -345,9 +771,11 @@ impl RaknetSocket {
         let recv_s = s.clone();
         let connected = Arc::new(tokio::sync::Semaphore::new(0));
         let connected_s = connected.clone();
+        let disconnect_reason = Arc::new(DisconnectCell::new());
+        let disconnect_reason_s = disconnect_reason.clone();
         let peer_addr = *addr;
         tokio::spawn(async move {
-            let mut buf = [0u8; 2048];
+            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
             loop {
                 if connected_s.is_closed() {
                     break;
@@ -370,6 +798,7 @@ impl RaknetSocket {
                             continue;
                         }
                         raknet_log_debug!("recv_from error : {}", e);
+                        disconnect_reason_s.set(DisconnectReason::SocketError);
                         connected_s.close();
                         break;
                     }
@@ -387,41 +816,48 @@ impl RaknetSocket {
             raknet_log_debug!("{} , recv_from finished", peer_addr);
         });
 
-        let (sender_sender, sender_receiver) = channel::<(Vec<u8>, SocketAddr, bool, u8)>(10);
-
         let ret = RaknetSocket {
             peer_addr: *addr,
             local_addr: s.local_addr().unwrap(),
-            user_data_receiver: Arc::new(Mutex::new(user_data_receiver)),
+            recv_buffer: Arc::new(Mutex::new(RecvBuffer::new())),
+            recv_notifier: Arc::new(Notify::new()),
             recvq: Arc::new(Mutex::new(RecvQ::new())),
             sendq,
             close_notifier: connected,
+            disconnect_reason,
             last_heartbeat_time: Arc::new(AtomicI64::new(cur_timestamp_millis())),
             enable_loss: Arc::new(AtomicBool::new(false)),
             loss_rate: Arc::new(AtomicU8::new(0)),
             incomming_notifier: Arc::new(Notify::new()),
-            sender: sender_sender,
+            flush_notifier: Arc::new(Notify::new()),
+            send_buffer_notifier: Arc::new(Notify::new()),
             drop_notifier: Arc::new(Notify::new()),
+            ping_state: Arc::new(PingState::new()),
+            stats: Arc::new(StatCounters::new(None)),
+            keepalive_interval: Arc::new(AtomicI64::new(DEFAULT_KEEPALIVE_INTERVAL)),
+            closing: AtomicBool::new(false),
             raknet_version,
         };
 
-        ret.start_receiver(&s, receiver, user_data_sender);
+        ret.start_receiver(&s, receiver);
         ret.start_tick(&s, None);
-        ret.start_sender(&s, sender_receiver);
         ret.drop_watcher().await;
 
         raknet_log_debug!("wait incomming notify");
-        ret.incomming_notifier.notified().await;
-
-        Ok(ret)
+        // the connection request is reliable , the ticker resends it until the deadline
+        match tokio::time::timeout_at(deadline, ret.wait_incomming()).await {
+            Ok(true) => Ok(ret),
+            Ok(false) => match ret.disconnect_reason.get() {
+                Some(DisconnectReason::ConnectionLost) | Some(DisconnectReason::Timeout) => {
+                    Err(RaknetError::ConnectTimeout(ConnectStage::ConnectionRequest))
+                }
+                _ => Err(ret.disconnect_reason.error()),
+            },
+            Err(_) => Err(RaknetError::ConnectTimeout(ConnectStage::ConnectionRequest)),
+        }
     }
 
-    fn start_receiver(
-        &self,
-        s: &Arc<UdpSocket>,
-        mut receiver: Receiver<Vec<u8>>,
-        user_data_sender: Sender<Vec<u8>>,
-    ) {
+    fn start_receiver(&self, s: &Arc<UdpSocket>, mut receiver: Receiver<Vec<u8>>) {
         let connected = self.close_notifier.clone();
         let peer_addr = self.peer_addr;
         let local_addr = self.local_addr;
@@ -429,6 +865,13 @@ impl RaknetSocket {
         let recvq = self.recvq.clone();
         let last_heartbeat_time = self.last_heartbeat_time.clone();
         let incomming_notify = self.incomming_notifier.clone();
+        let recv_buffer = self.recv_buffer.clone();
+        let recv_notifier = self.recv_notifier.clone();
+        let flush_notifier = self.flush_notifier.clone();
+        let send_buffer_notifier = self.send_buffer_notifier.clone();
+        let ping_state = self.ping_state.clone();
+        let stats = self.stats.clone();
+        let disconnect_reason = self.disconnect_reason.clone();
         let s = s.clone();
         let enable_loss = self.enable_loss.clone();
         let loss_rate = self.loss_rate.clone();
@@ -442,8 +885,11 @@ impl RaknetSocket {
                             &peer_addr,
                             &local_addr,
                             &sendq,
-                            &user_data_sender,
+                            &recv_buffer,
+                            &recv_notifier,
                             &incomming_notify,
+                            &ping_state,
+                            &stats,
                         )
                         .await
                         .unwrap();
@@ -455,14 +901,18 @@ impl RaknetSocket {
                     Some(buf) => buf,
                     None => {
                         raknet_log_debug!("channel receiver finished");
+                        // the listener dropped the session
+                        disconnect_reason.set(DisconnectReason::ListenerShutdown);
                         connected.close();
                         break;
                     }
                 };
 
                 last_heartbeat_time.store(cur_timestamp_millis(), Ordering::Relaxed);
+                stats.on_datagram_received(buf.len());
 
                 if PacketID::from(buf[0]).unwrap() == PacketID::Disconnect {
+                    disconnect_reason.set(DisconnectReason::RemoteDisconnect);
                     connected.close();
                     break;
                 }
@@ -472,33 +922,33 @@ impl RaknetSocket {
                     let mut sendq = sendq.write().await;
                     let ack = read_packet_ack(&buf).unwrap();
                     for i in 0..ack.record_count {
-                        if ack.sequences[i as usize].0 == ack.sequences[i as usize].1 {
-                            sendq.ack(ack.sequences[i as usize].0, cur_timestamp_millis());
-                        } else {
-                            for i in ack.sequences[i as usize].0..ack.sequences[i as usize].1 + 1 {
-                                sendq.ack(i, cur_timestamp_millis());
-                            }
-                        }
+                        sendq.ack_range(
+                            ack.sequences[i as usize].0,
+                            ack.sequences[i as usize].1,
+                            cur_timestamp_millis(),
+                        );
                     }
+                    // the send window may have opened
+                    flush_notifier.notify_one();
+                    send_buffer_notifier.notify_waiters();
                     continue;
                 }
 
                 if buf[0] == PacketID::Nack.to_u8() {
                     //handle nack
                     let nack = read_packet_nack(&buf).unwrap();
+                    stats.on_nack_received();
 
                     let mut sendq = sendq.write().await;
 
                     for i in 0..nack.record_count {
-                        if nack.sequences[i as usize].0 == nack.sequences[i as usize].1 {
-                            sendq.nack(nack.sequences[i as usize].0, cur_timestamp_millis());
-                        } else {
-                            for i in nack.sequences[i as usize].0..nack.sequences[i as usize].1 + 1
-                            {
-                                sendq.nack(i, cur_timestamp_millis());
-                            }
-                        }
+                        sendq.nack_range(
+                            nack.sequences[i as usize].0,
+                            nack.sequences[i as usize].1,
+                            cur_timestamp_millis(),
+                        );
                     }
+                    flush_notifier.notify_one();
                     continue;
                 }
 
@@ -509,23 +959,77 @@ impl RaknetSocket {
                     let frames = FrameVec::new(buf.clone()).unwrap();
 
                     let mut recvq = recvq.lock().await;
+                    if recvq.is_duplicate_datagram(frames.sequence_number) {
+                        raknet_log_debug!(
+                            "{} : drop duplicate datagram {}",
+                            peer_addr,
+                            frames.sequence_number
+                        );
+                        continue;
+                    }
+                    match recvq.check_window(&frames) {
+                        Ok(_) => {}
+                        Err(RaknetError::ReceiveWindowViolation) => {
+                            raknet_log_error!(
+                                "{} : receive window violated too many times , disconnect",
+                                peer_addr
+                            );
+                            disconnect_reason.set(DisconnectReason::ProtocolError);
+                            connected.close();
+                            continue;
+                        }
+                        Err(e) => {
+                            raknet_log_debug!(
+                                "{} : drop datagram {} , {:?}",
+                                peer_addr,
+                                frames.sequence_number,
+                                e
+                            );
+                            continue;
+                        }
+                    }
+
                     let mut is_break = false;
                     for frame in frames.frames {
-                        recvq.insert(frame).unwrap();
+                        match recvq.insert(frame) {
+                            Ok(_) => {}
+                            Err(
+                                e @ (RaknetError::CompoundTooLarge
+                                | RaknetError::TooManyCompounds
+                                | RaknetError::FragmentBufferFull),
+                            ) => {
+                                raknet_log_error!(
+                                    "{} : fragment limit exceeded , {:?} , disconnect",
+                                    peer_addr,
+                                    e
+                                );
+                                disconnect_reason.set(DisconnectReason::ProtocolError);
+                                connected.close();
+                                break;
+                            }
+                            Err(e) => {
+                                raknet_log_debug!("{} : drop frame , {:?}", peer_addr, e);
+                                continue;
+                            }
+                        }
 
                         for f in recvq.flush(&peer_addr) {
-                            if !RaknetSocket::handle(
+                            if let Some(reason) = RaknetSocket::handle(
                                 &f,
                                 &peer_addr,
                                 &local_addr,
                                 &sendq,
-                                &user_data_sender,
+                                &recv_buffer,
+                                &recv_notifier,
                                 &incomming_notify,
+                                &ping_state,
+                                &stats,
                             )
                             .await
                             .unwrap()
                             {
                                 raknet_log_info!("handle over");
+                                disconnect_reason.set(reason);
                                 connected.close();
                                 is_break = true;
                             };
@@ -552,6 +1056,7 @@ impl RaknetSocket {
                             &peer_addr,
                             enable_loss.load(Ordering::Relaxed),
                             loss_rate.load(Ordering::Relaxed),
+                            &stats,
                         )
                         .await
                         .unwrap();
@@ -561,48 +1066,12 @@ impl RaknetSocket {
                 }
             }
 
+            recv_buffer.lock().await.finished = true;
+            recv_notifier.notify_waiters();
             raknet_log_debug!("{} , receiver finished", peer_addr);
         });
     }
 
-    fn start_sender(
-        &self,
-        s: &Arc<UdpSocket>,
-        mut receiver: Receiver<(Vec<u8>, SocketAddr, bool, u8)>,
-    ) {
-        let connected = self.close_notifier.clone();
-        let s = s.clone();
-        tokio::spawn(async move {
-            loop {
-                tokio::select! {
-                    a = receiver.recv() => {
-                        match a {
-                            Some(p) => {
-                                match RaknetSocket::sendto(&s, &p.0, &p.1, p.2, p.3).await{
-                                    Ok(_) => {},
-                                    Err(e) => {
-                                        raknet_log_debug!("sendto error : {}" , e);
-                                        break;
-                                    },
-                                }
-                            },
-                            None => {
-                                raknet_log_debug!("sender worker's receiver channel closed");
-                                break;
-                            },
-                        };
-                    },
-                    _ = connected.acquire() => {
-                        raknet_log_debug!("sender close notified");
-                        break;
-                    }
-                }
-            }
-
-            raknet_log_debug!("sender worker closed");
-        });
-    }
-
     fn start_tick(&self, s: &Arc<UdpSocket>, collecter: Option<Arc<Mutex<Sender<SocketAddr>>>>) {
         let connected = self.close_notifier.clone();
         let s = s.clone();
@@ -613,16 +1082,33 @@ impl RaknetSocket {
         let enable_loss = self.enable_loss.clone();
         let loss_rate = self.loss_rate.clone();
         let last_heartbeat_time = self.last_heartbeat_time.clone();
+        let flush_notifier = self.flush_notifier.clone();
+        let send_buffer_notifier = self.send_buffer_notifier.clone();
+        let disconnect_reason = self.disconnect_reason.clone();
+        let keepalive_interval = self.keepalive_interval.clone();
+        let stats = self.stats.clone();
+        let mut last_ping_tick = cur_timestamp_millis();
         tokio::spawn(async move {
             loop {
-                sleep(std::time::Duration::from_millis(
-                    SendQ::DEFAULT_TIMEOUT_MILLS as u64,
-                ))
-                .await;
+                tokio::select! {
+                    _ = sleep(std::time::Duration::from_millis(
+                        SendQ::DEFAULT_TIMEOUT_MILLS as u64,
+                    )) => {},
+                    _ = flush_notifier.notified() => {}
+                }
 
                 // flush nack
                 let mut recvq = recvq.lock().await;
-                let nacks = recvq.get_nack();
+                if let Err(e) = recvq.tick(cur_timestamp_millis()) {
+                    raknet_log_error!(
+                        "{} : fragment limit exceeded , {:?} , disconnect",
+                        peer_addr,
+                        e
+                    );
+                    disconnect_reason.set(DisconnectReason::ProtocolError);
+                    connected.close();
+                }
+                let nacks = recvq.get_nack(cur_timestamp_millis());
                 if !nacks.is_empty() {
                     let nack = Nack {
                         record_count: nacks.len() as u16,
@@ -630,12 +1116,14 @@ impl RaknetSocket {
                     };
 
                     let buf = write_packet_nack(&nack).unwrap();
+                    stats.on_nack_sent();
                     RaknetSocket::sendto(
                         &s,
                         &buf,
                         &peer_addr,
                         enable_loss.load(Ordering::Relaxed),
                         loss_rate.load(Ordering::Relaxed),
+                        &stats,
                     )
                     .await
                     .unwrap();
@@ -643,26 +1131,56 @@ impl RaknetSocket {
 
                 //flush sendq
                 let mut sendq = sendq.write().await;
+
+                // keep the connection and the nat mapping alive , and measure the rtt
+                let interval = keepalive_interval.load(Ordering::Relaxed);
+                if interval > 0 && cur_timestamp_millis() - last_ping_tick >= interval {
+                    last_ping_tick = cur_timestamp_millis();
+                    let ping = ConnectedPing {
+                        client_timestamp: last_ping_tick,
+                    };
+                    // reliable so that a silent peer hits the retransmission limit
+                    sendq
+                        .insert(
+                            Reliability::Reliable,
+                            &write_packet_connected_ping(&ping).unwrap(),
+                        )
+                        .unwrap();
+                }
+
+                let retransmissions = sendq.get_retransmissions();
                 for f in sendq.flush(cur_timestamp_millis(), &peer_addr) {
-                    let data = f.serialize().unwrap();
+                    let data = f._serialize().unwrap();
+                    stats.on_data_datagram_sent();
                     RaknetSocket::sendto(
                         &s,
                         &data,
                         &peer_addr,
                         enable_loss.load(Ordering::Relaxed),
                         loss_rate.load(Ordering::Relaxed),
+                        &stats,
                     )
                     .await
                     .unwrap();
                 }
+                stats.on_retransmissions(sendq.get_retransmissions() - retransmissions);
+                // unreliable frames leave the buffer once they are sent
+                send_buffer_notifier.notify_waiters();
+
+                if sendq.is_lost() && !connected.is_closed() {
+                    raknet_log_error!("{} , peer stopped acking , connection lost", peer_addr);
+                    disconnect_reason.set(DisconnectReason::ConnectionLost);
+                    connected.close();
+                }
 
                 //monitor log
                 if cur_timestamp_millis() - last_monitor_tick > 10000 {
-                    raknet_log_debug!("peer addr : {} , sendq size : {} , sentq size : {} , rto : {} , recvq size : {} ,  recvq fragment size : {} , ordered queue size : {} - {:?}" , 
+                    raknet_log_debug!("peer addr : {} , sendq size : {} , sentq size : {} , rto : {} , cwnd : {} , recvq size : {} ,  recvq fragment size : {} , ordered queue size : {} - {:?}" , 
                         peer_addr,
                         sendq.get_reliable_queue_size(),
                         sendq.get_sent_queue_size(),
                         sendq.get_rto(),
+                        sendq.get_congestion_window(),
                         recvq.get_size(),
                         recvq.get_fragment_queue_size(),
                         recvq.get_ordered_packet(),
@@ -676,6 +1194,7 @@ impl RaknetSocket {
                     > RECEIVE_TIMEOUT
                 {
                     raknet_log_debug!("recv timeout");
+                    disconnect_reason.set(DisconnectReason::Timeout);
                     connected.close();
                     break;
                 }
@@ -688,6 +1207,7 @@ impl RaknetSocket {
                             &peer_addr,
                             enable_loss.load(Ordering::Relaxed),
                             loss_rate.load(Ordering::Relaxed),
+                            &stats,
                         )
                         .await
                         .unwrap();
@@ -696,16 +1216,13 @@ impl RaknetSocket {
                 }
             }
 
-            match collecter {
-                Some(p) => {
-                    match p.lock().await.send(peer_addr).await {
-                        Ok(_) => {}
-                        Err(e) => {
-                            raknet_log_error!("channel send error : {}", e);
-                        }
-                    };
-                }
-                None => {}
+            if let Some(p) = collecter {
+                match p.lock().await.send(peer_addr).await {
+                    Ok(_) => {}
+                    Err(e) => {
+                        raknet_log_error!("channel send error : {}", e);
+                    }
+                };
             }
             raknet_log_debug!("{} , ticker finished", peer_addr);
         });
@@ -726,11 +1243,70 @@ impl RaknetSocket {
                 .write()
                 .await
                 .insert(Reliability::Reliable, &[PacketID::Disconnect.to_u8()])?;
+            self.disconnect_reason.set(DisconnectReason::LocalClose);
             self.close_notifier.close();
         }
         Ok(())
     }
 
+    /// Close Raknet Socket after the peer received the packets already sent.
+    ///
+    /// New packets are refused with RaknetError::ConnectionClosed, then this method waits until every queued packet is acked,
+    /// sends a reliable disconnect notification and waits until it is acked too.
+    /// The connection is closed when `timeout` elapses whatever remains unacked.
+    /// Returns Ok(true) if everything was delivered in time.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.send(&[0xfe, 0x01], Reliability::ReliableOrdered).await.unwrap();
+    /// if !socket.close_graceful(std::time::Duration::from_secs(3)).await.unwrap() {
+    ///     // the peer may have missed the last packets
+    /// }
+    /// ```
+    pub async fn close_graceful(&self, timeout: std::time::Duration) -> Result<bool> {
+        if self.close_notifier.is_closed() || self.closing.swap(true, Ordering::Relaxed) {
+            return Ok(false);
+        }
+        let deadline = cur_timestamp_millis() + timeout.as_millis() as i64;
+
+        if !self.wait_acked(deadline).await {
+            raknet_log_debug!("{} , linger timeout , close", self.peer_addr);
+            self.close().await?;
+            return Ok(false);
+        }
+
+        // the peer answers with its own disconnect notification , which must not become the reason
+        self.disconnect_reason.set(DisconnectReason::LocalClose);
+        self.sendq
+            .write()
+            .await
+            .insert(Reliability::Reliable, &[PacketID::Disconnect.to_u8()])?;
+        self.flush_notifier.notify_one();
+        // the peer closes as soon as it gets the notification , its ack may never come
+        let delivered =
+            self.wait_acked(deadline).await || self.disconnect_reason.remote_disconnected();
+
+        self.close_notifier.close();
+        Ok(delivered)
+    }
+
+    // wait until every sent packet is acked , return false if the connection closed or the deadline passed first
+    async fn wait_acked(&self, deadline: i64) -> bool {
+        loop {
+            if self.close_notifier.is_closed() {
+                return false;
+            }
+            if self.sendq.read().await.is_empty() {
+                return true;
+            }
+            if cur_timestamp_millis() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+    }
+
     /// Unconnected ping a Raknet Server and return latency and motd.
     ///
     /// # Example
@@ -790,7 +1366,7 @@ impl RaknetSocket {
     ///
     /// packet must be `0xfe` as the first byte, using other values of bytes may cause unexpected errors.
     ///
-    /// Except Reliability::ReliableOrdered, all other reliability packets must be less than MTU - 60 (default 1340 bytes), otherwise RaknetError::PacketSizeExceedMTU will be returned
+    /// Packets larger than the MTU minus 60 bytes over IPv4 or 80 bytes over IPv6 (1432 bytes with a 1492 MTU) are split into fragments, unreliable packets that need to be split are sent as reliable.
     ///
     /// # Example
     /// ```ignore
@@ -798,37 +1374,200 @@ impl RaknetSocket {
     /// socket.send(&[0xfe], Reliability::ReliableOrdered).await.unwrap();
     /// ```
     pub async fn send(&self, buf: &[u8], r: Reliability) -> Result<()> {
-        if buf.is_empty() {
-            return Err(RaknetError::PacketHeaderError);
-        }
-
-        if buf[0] != 0xfe {
-            return Err(RaknetError::PacketHeaderError);
-        }
+        self.send_on_channel(buf, r, 0).await
+    }
 
-        if self.close_notifier.is_closed() {
-            return Err(RaknetError::ConnectionClosed);
+    /// Send a packet on one of the 32 ordering channels
+    ///
+    /// Ordered and sequenced packets are only ordered against packets sent on the same channel,
+    /// so a lost packet on one channel does not hold back the others. `send` uses channel 0.
+    ///
+    /// `channel` must be less than 32, otherwise RaknetError::IncorrectOrderChannel will be returned
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.send_on_channel(&[0xfe], Reliability::ReliableOrdered, 1).await.unwrap();
+    /// ```
+    pub async fn send_on_channel(&self, buf: &[u8], r: Reliability, channel: u8) -> Result<()> {
+        self.send_with_priority(buf, r, Priority::Immediate, channel)
+            .await
+    }
+
+    /// Send a packet with a priority on one of the 32 ordering channels
+    ///
+    /// Priority::Immediate packets are sent on the next flush, `send` and `send_on_channel` use it.
+    /// The other priorities wait for the next send tick (50ms), higher priorities go first but lower ones are never starved.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// // a chunk of the world , it must not delay the player input
+    /// socket.send_with_priority(&[0xfe; 100000], Reliability::ReliableOrdered, Priority::Low, 1).await.unwrap();
+    /// socket.send_with_priority(&[0xfe], Reliability::ReliableOrdered, Priority::High, 0).await.unwrap();
+    /// ```
+    pub async fn send_with_priority(
+        &self,
+        buf: &[u8],
+        r: Reliability,
+        priority: Priority,
+        channel: u8,
+    ) -> Result<()> {
+        if buf.is_empty() {
+            return Err(RaknetError::PacketHeaderError);
+        }
+
+        if buf[0] != 0xfe {
+            return Err(RaknetError::PacketHeaderError);
+        }
+
+        self.wait_send_buffer()
+            .await?
+            .insert_on_channel(r.clone(), buf, channel, priority)?;
+        self.stats.on_message_sent(&r);
+        if priority == Priority::Immediate {
+            // frames queued before the ticker wakes up will be packed into the same frame set
+            self.flush_notifier.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Send a packet without waiting for space in the send buffer
+    ///
+    /// Returns RaknetError::WouldBlock if the send buffer is full, see `set_send_buffer`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// match socket.try_send(&[0xfe], Reliability::Unreliable).await {
+    ///     Err(RaknetError::WouldBlock) => {
+    ///         // skip this position update
+    ///     }
+    ///     p => p.unwrap(),
+    /// }
+    /// ```
+    pub async fn try_send(&self, buf: &[u8], r: Reliability) -> Result<()> {
+        if buf.is_empty() {
+            return Err(RaknetError::PacketHeaderError);
+        }
+
+        if buf[0] != 0xfe {
+            return Err(RaknetError::PacketHeaderError);
+        }
+
+        if self.is_closing() {
+            return Err(self.closed_error());
         }
 
-        //flush sendq
         let mut sendq = self.sendq.write().await;
-        sendq.insert(r, buf)?;
-        let sender = self.sender.clone();
-        for f in sendq.flush(cur_timestamp_millis(), &self.peer_addr) {
-            let data = f.serialize().unwrap();
-            sender
-                .send((
-                    data,
-                    self.peer_addr,
-                    self.enable_loss.load(Ordering::Relaxed),
-                    self.loss_rate.load(Ordering::Relaxed),
-                ))
-                .await
-                .unwrap();
+        if sendq.is_buffer_full() {
+            return Err(RaknetError::WouldBlock);
         }
+        sendq.insert(r.clone(), buf)?;
+        self.stats.on_message_sent(&r);
+        self.flush_notifier.notify_one();
         Ok(())
     }
 
+    // the error returned by the methods of a closed connection
+    fn closed_error(&self) -> RaknetError {
+        if !self.close_notifier.is_closed() && self.closing.load(Ordering::Relaxed) {
+            return RaknetError::ConnectionClosed(DisconnectReason::LocalClose);
+        }
+        self.disconnect_reason.error()
+    }
+
+    // the connection refuses new packets
+    fn is_closing(&self) -> bool {
+        self.close_notifier.is_closed() || self.closing.load(Ordering::Relaxed)
+    }
+
+    /// Returns why the connection closed, None while it is open.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// if socket.recv().await.is_err() {
+    ///     match socket.disconnect_reason() {
+    ///         Some(DisconnectReason::RemoteDisconnect) => println!("server closed"),
+    ///         Some(DisconnectReason::Timeout) => println!("your connection timed out"),
+    ///         _ => {}
+    ///     }
+    /// }
+    /// ```
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        if !self.close_notifier.is_closed() {
+            return None;
+        }
+        self.disconnect_reason.get()
+    }
+
+    pub(crate) fn disconnect_cell(&self) -> Arc<DisconnectCell> {
+        self.disconnect_reason.clone()
+    }
+
+    // wait until the send buffer has space , return the locked sendq
+    async fn wait_send_buffer(&self) -> Result<RwLockWriteGuard<'_, SendQ>> {
+        loop {
+            // created before the check , so a wake up between the check and the wait is not missed
+            let notified = self.send_buffer_notifier.notified();
+
+            if self.is_closing() {
+                return Err(self.closed_error());
+            }
+
+            let sendq = self.sendq.write().await;
+            if !sendq.is_buffer_full() {
+                return Ok(sendq);
+            }
+            drop(sendq);
+
+            tokio::select! {
+                _ = notified => {}
+                _ = self.close_notifier.acquire() => {}
+            }
+        }
+    }
+
+    /// Send a packet and get a receipt which tells when the peer received it
+    ///
+    /// `r` must be one of Reliability::UnreliableWithAckReceipt, Reliability::ReliableWithAckReceipt or Reliability::ReliableOrderedWithAckReceipt,
+    /// otherwise RaknetError::IncorrectReliability will be returned.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let receipt = socket.send_with_receipt(&[0xfe], Reliability::ReliableOrderedWithAckReceipt).await.unwrap();
+    /// receipt.wait().await.unwrap();
+    /// ```
+    pub async fn send_with_receipt(&self, buf: &[u8], r: Reliability) -> Result<AckReceipt> {
+        if !r.with_ack_receipt() {
+            return Err(RaknetError::IncorrectReliability);
+        }
+
+        if buf.is_empty() {
+            return Err(RaknetError::PacketHeaderError);
+        }
+
+        if buf[0] != 0xfe {
+            return Err(RaknetError::PacketHeaderError);
+        }
+
+        let receiver = self.wait_send_buffer().await?.insert_with_receipt(
+            r.clone(),
+            buf,
+            0,
+            Priority::Immediate,
+        )?;
+        self.stats.on_message_sent(&r);
+        self.flush_notifier.notify_one();
+        Ok(AckReceipt {
+            receiver,
+            close_notifier: self.close_notifier.clone(),
+            disconnect_reason: self.disconnect_reason.clone(),
+        })
+    }
+
     /// Wait all packet acked
     ///
     /// # Example
@@ -841,7 +1580,7 @@ impl RaknetSocket {
         loop {
             {
                 if self.close_notifier.is_closed() {
-                    return Err(RaknetError::ConnectionClosed);
+                    return Err(self.closed_error());
                 }
                 let sendq = self.sendq.read().await;
                 if sendq.is_empty() {
@@ -852,6 +1591,98 @@ impl RaknetSocket {
         }
     }
 
+    /// Send a connected ping and wait for the pong, returns the round trip time in milliseconds.
+    ///
+    /// The ping is sent again every second until a pong arrives.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let rtt = socket.ping_rtt().await.unwrap();
+    /// ```
+    pub async fn ping_rtt(&self) -> Result<i64> {
+        let start = cur_timestamp_millis();
+        let mut last_ping_tick = 0;
+        loop {
+            // created before the check , so a pong between the check and the wait is not missed
+            let notified = self.ping_state.pong_notifier.notified();
+
+            if self.ping_state.last_pong.load(Ordering::Relaxed) >= start {
+                return Ok(self.ping_state.last_rtt.load(Ordering::Relaxed));
+            }
+
+            if self.close_notifier.is_closed() {
+                return Err(self.closed_error());
+            }
+
+            let now = cur_timestamp_millis();
+            if now - last_ping_tick >= 1000 {
+                last_ping_tick = now;
+                let ping = ConnectedPing {
+                    client_timestamp: now,
+                };
+                self.sendq.write().await.insert(
+                    Reliability::Unreliable,
+                    &write_packet_connected_ping(&ping)?,
+                )?;
+                self.flush_notifier.notify_one();
+            }
+
+            tokio::select! {
+                _ = notified => {}
+                _ = sleep(std::time::Duration::from_millis(1000)) => {}
+                _ = self.close_notifier.acquire() => {}
+            }
+        }
+    }
+
+    /// Returns the round trip time in milliseconds measured by the newest connected pong, None before the first pong.
+    ///
+    /// Connected pings are sent every `set_keepalive_interval` milliseconds, so this stays up to date without calling `ping_rtt`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// if let Some(rtt) = socket.last_rtt() {
+    ///     println!("ping : {}ms", rtt);
+    /// }
+    /// ```
+    pub fn last_rtt(&self) -> Option<i64> {
+        match self.ping_state.last_rtt.load(Ordering::Relaxed) {
+            -1 => None,
+            p => Some(p),
+        }
+    }
+
+    /// Returns the clock of the peer minus the local clock in milliseconds, estimated from the timestamps of the newest connected pong.
+    /// None before the first pong.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.ping_rtt().await.unwrap();
+    /// let server_time = cur_timestamp_millis() + socket.clock_offset().unwrap();
+    /// ```
+    pub fn clock_offset(&self) -> Option<i64> {
+        self.last_rtt()?;
+        Some(self.ping_state.clock_offset.load(Ordering::Relaxed))
+    }
+
+    /// Set the milliseconds between two connected pings, 0 disables them.
+    ///
+    /// The pings keep an idle connection and its NAT mapping alive, and measure `last_rtt`.
+    /// They are reliable, so an idle peer which goes silent is detected by `set_retransmission_limit`.
+    /// Default is 5 seconds.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.set_keepalive_interval(1000);
+    /// ```
+    pub fn set_keepalive_interval(&self, millis: i64) {
+        self.keepalive_interval.store(millis, Ordering::Relaxed);
+    }
+
     /// Recv a packet
     ///
     /// # Example
@@ -863,15 +1694,104 @@ impl RaknetSocket {
     /// }
     /// ```
     pub async fn recv(&self) -> Result<Vec<u8>> {
-        match self.user_data_receiver.lock().await.recv().await {
-            Some(p) => Ok(p),
-            None => {
-                if self.close_notifier.is_closed() {
-                    return Err(RaknetError::ConnectionClosed);
+        let (buf, _) = self.recv_with_info().await?;
+        Ok(buf)
+    }
+
+    /// Recv a packet with the reliability and ordering channel it was sent on
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let (buf, info) = socket.recv_with_info().await.unwrap();
+    /// if info.order_channel == 1 {
+    ///    //do something
+    /// }
+    /// ```
+    pub async fn recv_with_info(&self) -> Result<(Vec<u8>, RecvInfo)> {
+        loop {
+            // created before the check , so a packet pushed between the check and the wait is not missed
+            let notified = self.recv_notifier.notified();
+            {
+                let mut recv_buffer = self.recv_buffer.lock().await;
+                if let Some(p) = recv_buffer.pop() {
+                    return Ok(p);
+                }
+                if recv_buffer.finished {
+                    return Err(self.closed_error());
                 }
-                Err(RaknetError::SocketError)
             }
+            notified.await;
+        }
+    }
+
+    /// Set the maximum of bytes received but not read by `recv` yet, and what to do with a packet received when it is full.
+    ///
+    /// Acks are sent and control packets are handled whether or not the application reads, the buffer only holds user packets.
+    /// Default is 8MB and RecvOverflowPolicy::Disconnect.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// // position updates are useless when they are late
+    /// socket.set_recv_buffer(64 * 1024, RecvOverflowPolicy::DropOldest).await;
+    /// ```
+    pub async fn set_recv_buffer(&self, max_bytes: usize, policy: RecvOverflowPolicy) {
+        let mut recv_buffer = self.recv_buffer.lock().await;
+        recv_buffer.max_bytes = max_bytes;
+        recv_buffer.policy = policy;
+    }
+
+    /// Returns the bytes of the received packets which have not been read by `recv` yet.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let unread = socket.recv_buffer_bytes().await;
+    /// ```
+    pub async fn recv_buffer_bytes(&self) -> usize {
+        self.recv_buffer.lock().await.bytes
+    }
+
+    /// Returns the mtu negotiated in the handshake, ip and udp headers included.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let mtu = socket.mtu().await;
+    /// ```
+    pub async fn mtu(&self) -> u16 {
+        self.sendq.read().await.get_mtu()
+    }
+
+    /// Returns a snapshot of the statistics of this connection.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let stats = socket.stats().await;
+    /// println!("loss : {:.2}% , srtt : {}ms", stats.traffic.loss_rate() * 100.0, stats.srtt);
+    /// ```
+    pub async fn stats(&self) -> RaknetStats {
+        let mut ret = RaknetStats {
+            traffic: self.stats.snapshot(),
+            ..RaknetStats::default()
+        };
+        {
+            let sendq = self.sendq.read().await;
+            ret.rto = sendq.get_rto();
+            ret.srtt = sendq.get_srtt();
+            ret.send_queue_bytes = sendq.get_queued_bytes();
+            ret.inflight_bytes = sendq.get_inflight_bytes();
+            ret.unacked_datagrams = sendq.get_sent_queue_size();
+        }
+        {
+            let recvq = self.recvq.lock().await;
+            ret.recv_queue_size = recvq.get_ordered_packet();
+            ret.fragment_queue_size = recvq.get_fragment_queue_size();
         }
+        ret.recv_buffer_bytes = self.recv_buffer.lock().await.bytes;
+        ret
     }
 
     /// Returns the socket address of the remote peer of this Raknet connection.
@@ -915,9 +1835,224 @@ impl RaknetSocket {
         self.loss_rate.store(stage, Ordering::Relaxed);
     }
 
+    /// Set the send window of this connection.
+    ///
+    /// New packets are sent while older ones are still unacked, as long as there are less than `max_datagrams` datagrams and `max_bytes` bytes in flight.
+    /// Default is 512 datagrams and 1MB.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.set_send_window(1024, 4 * 1024 * 1024).await;
+    /// ```
+    pub async fn set_send_window(&self, max_datagrams: usize, max_bytes: usize) {
+        self.sendq
+            .write()
+            .await
+            .set_window(max_datagrams, max_bytes);
+    }
+
+    /// Set the maximum of bytes queued or waiting for acks on this connection.
+    ///
+    /// When the send buffer is full, `send` waits for acks to free space and `try_send` returns RaknetError::WouldBlock.
+    /// Default is 8MB.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.set_send_buffer(256 * 1024).await;
+    /// ```
+    pub async fn set_send_buffer(&self, max_bytes: usize) {
+        self.sendq.write().await.set_buffer(max_bytes);
+        self.send_buffer_notifier.notify_waiters();
+    }
+
+    /// Returns the bytes of the packets which have not been sent yet.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// if socket.queued_bytes().await > 64 * 1024 {
+    ///     // skip non-critical updates
+    /// }
+    /// ```
+    pub async fn queued_bytes(&self) -> usize {
+        self.sendq.read().await.get_queued_bytes()
+    }
+
+    /// Returns the bytes of the datagrams which have been sent but not acked yet.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let inflight = socket.inflight_bytes().await;
+    /// ```
+    pub async fn inflight_bytes(&self) -> usize {
+        self.sendq.read().await.get_inflight_bytes()
+    }
+
+    /// Set how much reordering this connection tolerates before it considers a datagram lost.
+    ///
+    /// It applies to the nacks sent to the peer and to the datagrams resent before the peer nacks them.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// // a mobile link reordering a lot
+    /// socket.set_reorder_threshold(ReorderThreshold { packets: 8, millis: 50 }).await;
+    /// ```
+    pub async fn set_reorder_threshold(&self, threshold: ReorderThreshold) {
+        self.recvq
+            .lock()
+            .await
+            .set_reorder_threshold(threshold.clone());
+        self.sendq.write().await.set_reorder_threshold(threshold);
+    }
+
+    /// Set when the peer of this connection is considered dead.
+    ///
+    /// The connection is closed when a datagram has been resent `max_retransmissions` times without an ack,
+    /// or when datagrams are waiting for an ack and none has been acked for `max_unacked_millis` milliseconds.
+    /// `send` and `recv` then return RaknetError::ConnectionLost.
+    /// Default is 16 retransmissions and 10 seconds.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.set_retransmission_limit(8, 3000).await;
+    /// match socket.recv().await {
+    ///     Err(RaknetError::ConnectionLost) => {
+    ///         // the player dropped
+    ///     }
+    ///     _ => {}
+    /// }
+    /// ```
+    pub async fn set_retransmission_limit(
+        &self,
+        max_retransmissions: u32,
+        max_unacked_millis: i64,
+    ) {
+        self.sendq
+            .write()
+            .await
+            .set_retransmission_limit(max_retransmissions, max_unacked_millis);
+    }
+
+    /// Set the constants of the retransmission timeout estimator (RFC 6298) of this connection.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.set_rto_options(RtoOptions {
+    ///     min_rto: 200,
+    ///     ..RtoOptions::default()
+    /// }).await;
+    /// ```
+    pub async fn set_rto_options(&self, options: RtoOptions) {
+        self.sendq.write().await.set_rto_options(options);
+    }
+
+    /// Returns the minimum round trip time in milliseconds, 0 before the first sample.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let min_rtt = socket.min_rtt().await;
+    /// ```
+    pub async fn min_rtt(&self) -> i64 {
+        self.sendq.read().await.get_min_rtt()
+    }
+
+    /// Returns the smoothed round trip time in milliseconds, 0 before the first sample.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let srtt = socket.smoothed_rtt().await;
+    /// ```
+    pub async fn smoothed_rtt(&self) -> i64 {
+        self.sendq.read().await.get_srtt()
+    }
+
+    /// Returns the round trip time variance in milliseconds, 0 before the first sample.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let jitter = socket.rtt_variance().await;
+    /// ```
+    pub async fn rtt_variance(&self) -> i64 {
+        self.sendq.read().await.get_rttvar()
+    }
+
+    /// Returns the current retransmission timeout in milliseconds.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let rto = socket.rto().await;
+    /// ```
+    pub async fn rto(&self) -> i64 {
+        self.sendq.read().await.get_rto()
+    }
+
+    /// Set the congestion controller of this connection, the default one is SlidingWindow.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.set_congestion_controller(Box::new(Cubic::new())).await;
+    /// ```
+    pub async fn set_congestion_controller(&self, controller: Box<dyn CongestionController>) {
+        self.sendq
+            .write()
+            .await
+            .set_congestion_controller(controller);
+    }
+
+    /// Set the maximum of bytes of the ordered packets waiting for a missing one.
+    ///
+    /// Datagrams beyond the receive window are dropped and nacked, a peer which keeps sending them is disconnected.
+    /// Default is 4MB.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.set_recv_window(1024 * 1024).await;
+    /// ```
+    pub async fn set_recv_window(&self, max_bytes: usize) {
+        self.recvq.lock().await.set_window(max_bytes);
+    }
+
+    /// Returns the bytes of the received packets waiting for a missing packet or fragment.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// let buffered = socket.recv_window_bytes().await;
+    /// ```
+    pub async fn recv_window_bytes(&self) -> usize {
+        self.recvq.lock().await.get_window_bytes()
+    }
+
+    /// Set the limits of the packets being reassembled from fragments, a peer exceeding them is disconnected.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let socket = RaknetSocket::connect("127.0.0.1:19132".parse().unwrap()).await.unwrap();
+    /// socket.set_fragment_limits(FragmentLimits {
+    ///     max_compound_size: 4096,
+    ///     ..FragmentLimits::default()
+    /// }).await;
+    /// ```
+    pub async fn set_fragment_limits(&self, limits: FragmentLimits) {
+        self.recvq.lock().await.set_fragment_limits(limits);
+    }
+
     async fn drop_watcher(&self) {
         let close_notifier = self.close_notifier.clone();
         let drop_notifier = self.drop_notifier.clone();
+        let disconnect_reason = self.disconnect_reason.clone();
         tokio::spawn(async move {
             raknet_log_debug!("socket drop watcher start");
             drop_notifier.notify_one();
@@ -929,6 +2064,7 @@ impl RaknetSocket {
                 return;
             }
 
+            disconnect_reason.set(DisconnectReason::LocalClose);
             close_notifier.close();
 
             raknet_log_debug!("socket drop watcher closed");
@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::arq::Reliability;
+
+/// Traffic counters of a connection, or of all the connections of a listener.
+///
+/// `messages_sent` and `messages_received` are indexed by `Reliability::to_u8()`.
+#[derive(Clone, Debug, Default)]
+pub struct TrafficStats {
+    /// bytes of the udp payloads sent , including acks and retransmissions
+    pub bytes_sent: u64,
+    /// bytes of the udp payloads received
+    pub bytes_received: u64,
+    pub datagrams_sent: u64,
+    pub datagrams_received: u64,
+    /// frame set datagrams sent , without acks , nacks and other control datagrams
+    pub data_datagrams_sent: u64,
+    /// user packets sent per reliability
+    pub messages_sent: [u64; 8],
+    /// user packets received per reliability , as sent on the wire
+    pub messages_received: [u64; 8],
+    /// datagrams sent again because they were lost
+    pub retransmissions: u64,
+    pub nacks_sent: u64,
+    pub nacks_received: u64,
+}
+
+impl TrafficStats {
+    /// Estimated packet loss rate , the share of the sent frame set datagrams which were retransmissions.
+    pub fn loss_rate(&self) -> f64 {
+        if self.data_datagrams_sent == 0 {
+            return 0.0;
+        }
+        self.retransmissions as f64 / self.data_datagrams_sent as f64
+    }
+}
+
+/// Snapshot of the statistics of a connection, see `RaknetSocket::stats`.
+#[derive(Clone, Debug, Default)]
+pub struct RaknetStats {
+    pub traffic: TrafficStats,
+    /// retransmission timeout in milliseconds
+    pub rto: i64,
+    /// smoothed round trip time in milliseconds , 0 before the first sample
+    pub srtt: i64,
+    /// bytes queued but not sent yet
+    pub send_queue_bytes: usize,
+    /// bytes sent but not acked yet
+    pub inflight_bytes: usize,
+    /// datagrams sent but not acked yet
+    pub unacked_datagrams: usize,
+    /// ordered frames received but not delivered yet , waiting for missing ones
+    pub recv_queue_size: usize,
+    /// packets being reassembled from fragments
+    pub fragment_queue_size: usize,
+    /// bytes received but not read by `recv` yet
+    pub recv_buffer_bytes: usize,
+}
+
+/// Snapshot of the statistics of a listener, see `RaknetListener::stats`.
+#[derive(Clone, Debug, Default)]
+pub struct ListenerStats {
+    /// sessions which finished the handshake
+    pub sessions: usize,
+    /// traffic of all the sessions since the listener started , closed ones included
+    pub traffic: TrafficStats,
+}
+
+// live counters , every update is added to the parent too
+pub(crate) struct StatCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    datagrams_sent: AtomicU64,
+    datagrams_received: AtomicU64,
+    data_datagrams_sent: AtomicU64,
+    messages_sent: [AtomicU64; 8],
+    messages_received: [AtomicU64; 8],
+    retransmissions: AtomicU64,
+    nacks_sent: AtomicU64,
+    nacks_received: AtomicU64,
+    parent: Option<Arc<StatCounters>>,
+}
+
+impl StatCounters {
+    pub fn new(parent: Option<Arc<StatCounters>>) -> Self {
+        Self {
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            datagrams_sent: AtomicU64::new(0),
+            datagrams_received: AtomicU64::new(0),
+            data_datagrams_sent: AtomicU64::new(0),
+            messages_sent: Default::default(),
+            messages_received: Default::default(),
+            retransmissions: AtomicU64::new(0),
+            nacks_sent: AtomicU64::new(0),
+            nacks_received: AtomicU64::new(0),
+            parent,
+        }
+    }
+
+    fn parent(&self) -> Option<&StatCounters> {
+        self.parent.as_deref()
+    }
+
+    pub fn on_datagram_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.datagrams_sent.fetch_add(1, Ordering::Relaxed);
+        if let Some(p) = self.parent() {
+            p.on_datagram_sent(bytes);
+        }
+    }
+
+    pub fn on_data_datagram_sent(&self) {
+        self.data_datagrams_sent.fetch_add(1, Ordering::Relaxed);
+        if let Some(p) = self.parent() {
+            p.on_data_datagram_sent();
+        }
+    }
+
+    pub fn on_datagram_received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.datagrams_received.fetch_add(1, Ordering::Relaxed);
+        if let Some(p) = self.parent() {
+            p.on_datagram_received(bytes);
+        }
+    }
+
+    pub fn on_message_sent(&self, r: &Reliability) {
+        self.messages_sent[r.to_u8() as usize].fetch_add(1, Ordering::Relaxed);
+        if let Some(p) = self.parent() {
+            p.on_message_sent(r);
+        }
+    }
+
+    pub fn on_message_received(&self, r: &Reliability) {
+        self.messages_received[r.to_u8() as usize].fetch_add(1, Ordering::Relaxed);
+        if let Some(p) = self.parent() {
+            p.on_message_received(r);
+        }
+    }
+
+    pub fn on_retransmissions(&self, count: u64) {
+        self.retransmissions.fetch_add(count, Ordering::Relaxed);
+        if let Some(p) = self.parent() {
+            p.on_retransmissions(count);
+        }
+    }
+
+    pub fn on_nack_sent(&self) {
+        self.nacks_sent.fetch_add(1, Ordering::Relaxed);
+        if let Some(p) = self.parent() {
+            p.on_nack_sent();
+        }
+    }
+
+    pub fn on_nack_received(&self) {
+        self.nacks_received.fetch_add(1, Ordering::Relaxed);
+        if let Some(p) = self.parent() {
+            p.on_nack_received();
+        }
+    }
+
+    pub fn snapshot(&self) -> TrafficStats {
+        TrafficStats {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            datagrams_sent: self.datagrams_sent.load(Ordering::Relaxed),
+            datagrams_received: self.datagrams_received.load(Ordering::Relaxed),
+            data_datagrams_sent: self.data_datagrams_sent.load(Ordering::Relaxed),
+            messages_sent: std::array::from_fn(|i| self.messages_sent[i].load(Ordering::Relaxed)),
+            messages_received: std::array::from_fn(|i| {
+                self.messages_received[i].load(Ordering::Relaxed)
+            }),
+            retransmissions: self.retransmissions.load(Ordering::Relaxed),
+            nacks_sent: self.nacks_sent.load(Ordering::Relaxed),
+            nacks_received: self.nacks_received.load(Ordering::Relaxed),
+        }
+    }
+}
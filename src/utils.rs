@@ -2,8 +2,18 @@ pub const RAKNET_PROTOCOL_VERSION: u8 = 7;
 pub const RAKNET_PROTOCOL_VERSION_LIST: [u8; 2] = [7, 8];
 //the MTU is minecraft bedrock 1.18.2 give me
 pub const RAKNET_CLIENT_MTU: u16 = 1400;
+/// mtu sizes the client tries in the handshake , from the largest
+pub const RAKNET_MTU_SIZES: [u16; 3] = [1492, 1200, 576];
+pub const RAKNET_MIN_MTU: u16 = 576;
+pub const RAKNET_MAX_MTU: u16 = 1492;
+/// large enough for any udp datagram
+pub const MAX_DATAGRAM_SIZE: usize = 65535;
 
 pub const RECEIVE_TIMEOUT: i64 = 60000;
+pub const DEFAULT_KEEPALIVE_INTERVAL: i64 = 5000;
+
+/// raknet has 32 independent ordering channels
+pub const NUMBER_OF_ORDER_CHANNELS: u8 = 32;
 
 pub enum Endian {
     Big,
@@ -23,3 +33,44 @@ pub fn _is_timeout(time: i64, timeout: u64) -> bool {
     let cur = cur_timestamp_millis();
     cur >= time + timeout as i64
 }
+
+/// raknet sequence numbers and frame indices are u24 on the wire
+pub const U24_MAX: u32 = 0xffffff;
+
+/// a + b on a u24 counter
+pub fn u24_add(a: u32, b: u32) -> u32 {
+    a.wrapping_add(b) & U24_MAX
+}
+
+/// distance from b to a on a u24 counter
+pub fn u24_sub(a: u32, b: u32) -> u32 {
+    a.wrapping_sub(b) & U24_MAX
+}
+
+/// serial number arithmetic (RFC 1982), a is newer than b if it is less than half of the u24 space ahead of b
+pub fn u24_gt(a: u32, b: u32) -> bool {
+    a != b && u24_sub(a, b) < 0x800000
+}
+
+pub fn u24_ge(a: u32, b: u32) -> bool {
+    a == b || u24_gt(a, b)
+}
+
+pub fn u24_lt(a: u32, b: u32) -> bool {
+    !u24_ge(a, b)
+}
+
+/// a..=b on a u24 counter, empty if b is older than a
+pub fn u24_range(a: u32, b: u32) -> impl Iterator<Item = u32> {
+    let len = if u24_ge(b, a) { u24_sub(b, a) + 1 } else { 0 };
+    (0..len).map(move |i| u24_add(a, i))
+}
+
+/// ip header + udp header , the part of the mtu which is not udp payload
+pub fn udp_overhead(addr: &std::net::SocketAddr) -> usize {
+    if addr.is_ipv6() {
+        48
+    } else {
+        28
+    }
+}